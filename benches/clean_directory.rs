@@ -0,0 +1,117 @@
+//! Measures how many files per second lineman cleans, over a generated directory of dirty files, and how many lines
+//! per second it writes for a file made of many short lines
+//!
+//! Run with `cargo bench`. The fixture is generated from fixed content, so every run cleans exactly the same files
+
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion, Throughput};
+use lineman::{clean_stream, clean_string, CleanOptions, LineEnding};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// The number of files in each generated directory
+const FILE_COUNTS: [usize; 2] = [100, 1000];
+
+/// The number of lines in each generated file
+const LINE_COUNT: usize = 200;
+
+/// The number of lines in the file of short lines, whose cleaning is dominated by the cost of each write
+const SHORT_LINE_COUNT: usize = 100_000;
+
+/// The content of one fixture file. Each file mixes lines that are already clean with trailing spaces, trailing tabs
+/// and CRLF line endings, and ends in several blank lines, so that every rule that runs by default has work to do
+fn fixture_content(file_index: usize) -> String {
+    let mut content = String::new();
+
+    for line_index in 0..LINE_COUNT {
+        let line = format!(
+            "    let value_{} = {} + {};",
+            line_index, file_index, line_index
+        );
+
+        match line_index % 4 {
+            0 => content.push_str(&format!("{}\n", line)),
+            1 => content.push_str(&format!("{}   \n", line)),
+            2 => content.push_str(&format!("{}\t\n", line)),
+            _ => content.push_str(&format!("{}\r\n", line)),
+        }
+    }
+
+    content.push_str("\n\n\n");
+    content
+}
+
+/// Writes a fresh copy of the fixture, spread over a few nested directories
+fn write_fixture(root_path: &Path, file_count: usize) -> Vec<PathBuf> {
+    (0..file_count)
+        .map(|file_index| {
+            let path = root_path
+                .join(format!("module_{}", file_index % 10))
+                .join(format!("file_{}.rs", file_index));
+
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, fixture_content(file_index)).unwrap();
+            path
+        })
+        .collect()
+}
+
+/// Cleans each file the way lineman does, only writing the files that change
+fn clean_files(paths: &[PathBuf], options: &CleanOptions) {
+    for path in paths {
+        let content = fs::read_to_string(path).unwrap();
+        let cleaned_content = clean_string(&content, options);
+
+        if cleaned_content != content {
+            fs::write(path, cleaned_content).unwrap();
+        }
+    }
+}
+
+fn bench_clean_directory(criterion: &mut Criterion) {
+    let options = CleanOptions::default();
+    let mut group = criterion.benchmark_group("clean_directory");
+
+    for file_count in FILE_COUNTS {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        // Reported as files per second
+        group.throughput(Throughput::Elements(file_count as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(file_count),
+            &file_count,
+            |bencher, &file_count| {
+                bencher.iter_batched(
+                    || write_fixture(temp_dir.path(), file_count),
+                    |paths| clean_files(&paths, &options),
+                    BatchSize::PerIteration,
+                )
+            },
+        );
+    }
+
+    group.finish();
+}
+
+fn bench_write_short_lines(criterion: &mut Criterion) {
+    let options = CleanOptions::default();
+    let content = "x \n".repeat(SHORT_LINE_COUNT);
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("short_lines.txt");
+    let mut group = criterion.benchmark_group("write_short_lines");
+
+    // Reported as lines per second
+    group.throughput(Throughput::Elements(SHORT_LINE_COUNT as u64));
+    group.bench_function(BenchmarkId::from_parameter(SHORT_LINE_COUNT), |bencher| {
+        bencher.iter(|| {
+            let mut writer = BufWriter::new(File::create(&path).unwrap());
+            clean_stream(content.as_bytes(), &mut writer, &options, LineEnding::Lf).unwrap();
+            writer.flush().unwrap();
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_clean_directory, bench_write_short_lines);
+criterion_main!(benches);
@@ -0,0 +1,168 @@
+use crate::report::Report;
+use filetime::FileTime;
+use lineman::CleanOptions;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// The modification time and size a file had when it was last known to be clean, and the cleaning options it was
+/// clean under. Those are kept for each file, since `.editorconfig` settings and `--markdown` change them per file
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CacheEntry {
+    modified_seconds: i64,
+    modified_nanoseconds: u32,
+    size: u64,
+    clean_options: String,
+}
+
+impl CacheEntry {
+    fn of(path: &Path, clean_options: &CleanOptions) -> io::Result<Self> {
+        let metadata = fs::metadata(path)?;
+        let modified = FileTime::from_last_modification_time(&metadata);
+
+        Ok(CacheEntry {
+            modified_seconds: modified.unix_seconds(),
+            modified_nanoseconds: modified.nanoseconds(),
+            size: metadata.len(),
+            clean_options: format!("{:?}", clean_options),
+        })
+    }
+}
+
+/// The files that were clean at the end of a previous run, used by `--cache` to skip files that haven't changed
+/// since. A file is only skipped while both its modification time and its size match its entry
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Cache {
+    /// The options the files were read and reported with, since a file that is clean and reports nothing under one
+    /// set of options can be dirty or report lines under another
+    settings: String,
+    files: BTreeMap<String, CacheEntry>,
+}
+
+impl Cache {
+    /// Reads the cache at the given path, starting over with an empty cache if there is none yet, if it can't be
+    /// read, or if it was written with different read or report settings. The cache only saves time, so losing it is
+    /// never an error
+    pub fn load(cache_path: &Path, settings: String) -> Self {
+        fs::read_to_string(cache_path)
+            .ok()
+            .and_then(|cache_string| serde_json::from_str::<Cache>(&cache_string).ok())
+            .filter(|cache| cache.settings == settings)
+            .unwrap_or(Cache {
+                settings,
+                files: BTreeMap::new(),
+            })
+    }
+
+    /// Whether a file is unchanged since it was last recorded as clean under the same cleaning options
+    pub fn is_fresh(&self, path: &Path, clean_options: &CleanOptions) -> bool {
+        self.files
+            .get(&path.to_string_lossy().into_owned())
+            .is_some_and(|cache_entry| {
+                CacheEntry::of(path, clean_options).ok().as_ref() == Some(cache_entry)
+            })
+    }
+
+    /// Records a file as clean under the given cleaning options as it is now, or forgets it if it can no longer be
+    /// read
+    pub fn insert(&mut self, path: &Path, clean_options: &CleanOptions) {
+        let key = path.to_string_lossy().into_owned();
+
+        match CacheEntry::of(path, clean_options) {
+            Ok(cache_entry) => self.files.insert(key, cache_entry),
+            Err(_) => self.files.remove(&key),
+        };
+    }
+
+    pub fn remove(&mut self, path: &Path) {
+        self.files.remove(&path.to_string_lossy().into_owned());
+    }
+
+    /// Records every file a run left clean under the cleaning options it was cleaned with, and forgets every other
+    /// file it processed. Files with long lines are forgotten too, since skipping them would drop those lines from the
+    /// report
+    pub fn update(
+        &mut self,
+        report: &Report,
+        file_clean_options: impl Fn(&Path) -> Option<CleanOptions>,
+    ) {
+        let clean_file_paths = report
+            .already_clean_file_paths
+            .iter()
+            .chain(report.cleaned_files.iter().map(|(path, _)| path));
+
+        for path in clean_file_paths {
+            match file_clean_options(path) {
+                Some(clean_options) => self.insert(path, &clean_options),
+                None => self.remove(path),
+            }
+        }
+
+        let other_file_paths = report
+            .would_be_cleaned_files
+            .iter()
+            .map(|(path, _)| path)
+            .chain(report.unreadable_files.iter().map(|(path, _)| path))
+            .chain(report.unwritable_files.iter().map(|(path, _)| path))
+            .chain(report.read_only_files.iter().map(|(path, _)| path))
+            .chain(report.not_backed_up_files.iter().map(|(path, _)| path))
+            .chain(&report.binary_file_paths)
+            .chain(report.oversized_files.iter().map(|(path, _)| path))
+            .chain(&report.vanished_file_paths)
+            .chain(report.long_line_files.iter().map(|(path, _)| path));
+
+        for path in other_file_paths {
+            self.remove(path);
+        }
+    }
+
+    /// Writes the cache to a temporary file next to the given path and renames it into place, so that an interrupted
+    /// run never leaves a partially written cache behind
+    pub fn save(&self, cache_path: &Path) -> io::Result<()> {
+        let cache_string = serde_json::to_string(self)?;
+        let temp_path = crate::temp_file_path(cache_path);
+
+        fs::write(&temp_path, cache_string)
+            .and_then(|()| fs::rename(&temp_path, cache_path))
+            .inspect_err(|_| {
+                let _ = fs::remove_file(&temp_path);
+            })
+    }
+}
+
+#[test]
+fn cache_files_until_their_mtime_or_size_changes() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cache_path = temp_dir.path().join("lineman-cache.json");
+    let path = temp_dir.path().join("main.rs");
+    fs::write(&path, "content\n").unwrap();
+
+    let mtime = FileTime::from_unix_time(1_000_000_000, 0);
+    filetime::set_file_mtime(&path, mtime).unwrap();
+
+    let clean_options = CleanOptions::default();
+    let mut cache = Cache::load(&cache_path, String::from("settings"));
+    assert!(!cache.is_fresh(&path, &clean_options));
+
+    cache.insert(&path, &clean_options);
+    cache.save(&cache_path).unwrap();
+
+    let cache = Cache::load(&cache_path, String::from("settings"));
+    assert!(cache.is_fresh(&path, &clean_options));
+
+    let other_options = CleanOptions {
+        tabs_to_spaces: true,
+        ..CleanOptions::default()
+    };
+    assert!(!cache.is_fresh(&path, &other_options));
+    assert!(
+        !Cache::load(&cache_path, String::from("other settings")).is_fresh(&path, &clean_options)
+    );
+
+    // A change in size invalidates the entry even though the modification time is the same
+    fs::write(&path, "more content\n").unwrap();
+    filetime::set_file_mtime(&path, mtime).unwrap();
+    assert!(!cache.is_fresh(&path, &clean_options));
+}
@@ -0,0 +1,81 @@
+use lineman::LineEnding;
+use serde::{Deserialize, Deserializer};
+use std::fs;
+use std::path::Path;
+
+/// The configuration file looked for in the working directory when none is given explicitly
+pub const DEFAULT_CONFIG_FILE_NAME: &str = "lineman.toml";
+
+/// Settings read from a `lineman.toml` file. Every setting is optional, and anything passed on the command line takes
+/// precedence over it
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub extensions: Option<Vec<String>>,
+    pub exclude: Option<Vec<String>>,
+    #[serde(default, deserialize_with = "deserialize_line_ending")]
+    pub line_ending: Option<LineEnding>,
+    pub normalize_eof_newlines: Option<bool>,
+}
+
+impl Config {
+    /// Reads the configuration file at the given path, or `lineman.toml` in the working directory if there is one.
+    /// Returns `None` when no path is given and there is no default configuration file
+    pub fn load(config_path: Option<&Path>) -> Result<Option<Config>, String> {
+        let config_path = match config_path {
+            Some(config_path) => config_path,
+            None if Path::new(DEFAULT_CONFIG_FILE_NAME).is_file() => {
+                Path::new(DEFAULT_CONFIG_FILE_NAME)
+            }
+            None => return Ok(None),
+        };
+        let config_string = fs::read_to_string(config_path).map_err(|error| {
+            format!(
+                "The config file could not be read: {}: {}",
+                config_path.display(),
+                error
+            )
+        })?;
+
+        toml::from_str(&config_string).map(Some).map_err(|error| {
+            format!(
+                "The config file is invalid: {}: {}",
+                config_path.display(),
+                error
+            )
+        })
+    }
+}
+
+fn deserialize_line_ending<'de, D>(deserializer: D) -> Result<Option<LineEnding>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|line_ending| line_ending.parse().map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[test]
+fn parse_every_config_setting() {
+    let config: Config = toml::from_str(
+        r#"
+        extensions = ["rs", "py"]
+        exclude = ["target/**"]
+        line_ending = "crlf"
+        normalize_eof_newlines = false
+        "#,
+    )
+    .unwrap();
+
+    assert_eq!(
+        config.extensions,
+        Some(vec!["rs".to_string(), "py".to_string()])
+    );
+    assert_eq!(config.exclude, Some(vec!["target/**".to_string()]));
+    assert_eq!(config.line_ending, Some(LineEnding::Crlf));
+    assert_eq!(config.normalize_eof_newlines, Some(false));
+
+    assert!(toml::from_str::<Config>(r#"line_ending = "nel""#).is_err());
+    assert!(toml::from_str::<Config>("unknown = true").is_err());
+}
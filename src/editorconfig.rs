@@ -0,0 +1,114 @@
+use ec4rs::property::{Charset, EndOfLine, FinalNewline, TrimTrailingWs};
+use lineman::{CleanOptions, LineEnding};
+use std::io;
+use std::path::Path;
+
+/// Resolves the `.editorconfig` settings that apply to a file and layers them over the cleaning options used for
+/// every other file. Settings the `.editorconfig` files don't declare keep their values from the given options
+pub fn editorconfig_clean_options(
+    path: &Path,
+    clean_options: &CleanOptions,
+) -> io::Result<CleanOptions> {
+    let properties = ec4rs::properties_of(path)
+        .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error.to_string()))?;
+    let mut clean_options = clean_options.clone();
+
+    if let Ok(TrimTrailingWs::Value(trim_trailing_whitespace)) = properties.get::<TrimTrailingWs>()
+    {
+        clean_options.trim_trailing_whitespace = trim_trailing_whitespace;
+    }
+
+    // Not inserting a final newline still leaves trailing blank lines to be trimmed, as long as EOF newline
+    // normalization is on
+    if let Ok(FinalNewline::Value(insert_final_newline)) = properties.get::<FinalNewline>() {
+        clean_options.normalize_trailing_blanks_only = !insert_final_newline;
+
+        if insert_final_newline {
+            clean_options.normalize_eof_newlines = true;
+        }
+    }
+
+    match properties.get::<EndOfLine>() {
+        Ok(EndOfLine::Lf) => clean_options.line_ending = Some(LineEnding::Lf),
+        Ok(EndOfLine::CrLf) => clean_options.line_ending = Some(LineEnding::Crlf),
+        Ok(EndOfLine::Cr) => clean_options.line_ending = Some(LineEnding::Cr),
+        Err(_) => {}
+    }
+
+    // The encoding of each file is detected from its content, so the charset only decides what happens to a UTF-8
+    // byte order mark
+    match properties.get::<Charset>() {
+        Ok(Charset::Utf8) => clean_options.strip_bom = true,
+        Ok(Charset::Utf8Bom) => clean_options.strip_bom = false,
+        Ok(Charset::Latin1) | Ok(Charset::Utf16Le) | Ok(Charset::Utf16Be) | Err(_) => {}
+    }
+
+    Ok(clean_options)
+}
+
+#[test]
+fn apply_editorconfig_settings_for_matching_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        temp_dir.path().join(".editorconfig"),
+        "root = true\n\
+         \n\
+         [*]\n\
+         end_of_line = crlf\n\
+         charset = utf-8\n\
+         \n\
+         [*.md]\n\
+         trim_trailing_whitespace = false\n\
+         insert_final_newline = false\n",
+    )
+    .unwrap();
+
+    let rust_options =
+        editorconfig_clean_options(&temp_dir.path().join("main.rs"), &CleanOptions::default())
+            .unwrap();
+
+    assert_eq!(rust_options.line_ending, Some(LineEnding::Crlf));
+    assert!(rust_options.strip_bom);
+    assert!(rust_options.trim_trailing_whitespace);
+    assert!(rust_options.normalize_eof_newlines);
+
+    let markdown_options =
+        editorconfig_clean_options(&temp_dir.path().join("README.md"), &CleanOptions::default())
+            .unwrap();
+
+    assert!(!markdown_options.trim_trailing_whitespace);
+    assert!(markdown_options.normalize_eof_newlines);
+    assert!(markdown_options.normalize_trailing_blanks_only);
+}
+
+#[test]
+fn trim_trailing_blank_lines_without_inserting_a_final_newline() {
+    use lineman::clean_string;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    std::fs::write(
+        temp_dir.path().join(".editorconfig"),
+        "root = true\n\
+         \n\
+         [*]\n\
+         insert_final_newline = false\n",
+    )
+    .unwrap();
+
+    let options =
+        editorconfig_clean_options(&temp_dir.path().join("main.rs"), &CleanOptions::default())
+            .unwrap();
+
+    assert_eq!(clean_string("content\n\n\n", &options), "content\n");
+    assert_eq!(clean_string("content\n\n  ", &options), "content\n");
+    assert_eq!(clean_string("content", &options), "content");
+
+    let disabled_options = CleanOptions {
+        normalize_eof_newlines: false,
+        ..CleanOptions::default()
+    };
+    let options =
+        editorconfig_clean_options(&temp_dir.path().join("main.rs"), &disabled_options).unwrap();
+
+    assert_eq!(clean_string("content\n\n\n", &options), "content\n\n\n");
+}
@@ -0,0 +1,83 @@
+use std::borrow::Cow;
+
+const UTF_16_LE_BOM: [u8; 2] = [0xFF, 0xFE];
+const UTF_16_BE_BOM: [u8; 2] = [0xFE, 0xFF];
+
+/// The text encoding of a file's raw bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    /// Detects the encoding of raw bytes from their byte order mark, falling back to UTF-8 when there is none
+    pub fn detect(bytes: &[u8]) -> Self {
+        if bytes.starts_with(&UTF_16_LE_BOM) {
+            Encoding::Utf16Le
+        } else if bytes.starts_with(&UTF_16_BE_BOM) {
+            Encoding::Utf16Be
+        } else {
+            Encoding::Utf8
+        }
+    }
+
+    /// Decodes raw bytes into a string, returning `None` if they aren't valid in this encoding. UTF-16 byte order
+    /// marks are left out of the decoded string, while a UTF-8 byte order mark is kept
+    pub fn decode(&self, bytes: &[u8]) -> Option<String> {
+        let to_code_unit = match self {
+            Encoding::Utf8 => return String::from_utf8(bytes.to_vec()).ok(),
+            Encoding::Utf16Le => u16::from_le_bytes,
+            Encoding::Utf16Be => u16::from_be_bytes,
+        };
+        let bytes = &bytes[self.bom().len()..];
+
+        if !bytes.len().is_multiple_of(2) {
+            return None;
+        }
+
+        let code_units: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|chunk| to_code_unit([chunk[0], chunk[1]]))
+            .collect();
+
+        String::from_utf16(&code_units).ok()
+    }
+
+    /// The byte order mark that starts encoded content, which is empty for UTF-8 since its byte order mark is part
+    /// of the decoded string
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => &[],
+            Encoding::Utf16Le => &UTF_16_LE_BOM,
+            Encoding::Utf16Be => &UTF_16_BE_BOM,
+        }
+    }
+
+    /// Encodes a string into raw bytes, without a byte order mark
+    pub fn encode<'a>(&self, text: &'a str) -> Cow<'a, [u8]> {
+        let from_code_unit = match self {
+            Encoding::Utf8 => return Cow::Borrowed(text.as_bytes()),
+            Encoding::Utf16Le => u16::to_le_bytes,
+            Encoding::Utf16Be => u16::to_be_bytes,
+        };
+
+        Cow::Owned(text.encode_utf16().flat_map(from_code_unit).collect())
+    }
+}
+
+#[test]
+fn decode_and_encode_utf_16_be() {
+    let bytes = [0xFE, 0xFF, 0x00, b'h', 0x00, b'i', 0x00, b'\n'];
+    let encoding = Encoding::detect(&bytes);
+
+    assert_eq!(encoding, Encoding::Utf16Be);
+    assert_eq!(encoding.decode(&bytes), Some("hi\n".to_string()));
+    assert_eq!([encoding.bom(), &encoding.encode("hi\n")].concat(), bytes);
+}
+
+#[test]
+fn reject_odd_length_utf_16() {
+    assert_eq!(Encoding::Utf16Le.decode(&[0xFF, 0xFE, b'h']), None);
+}
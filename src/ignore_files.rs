@@ -0,0 +1,120 @@
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The gitignore-style file that excludes paths from lineman alone, without affecting git
+pub const LINEMANIGNORE_FILE_NAME: &str = ".linemanignore";
+
+/// Decides whether paths are excluded by gitignore-style files (such as `.gitignore`) found in the directories
+/// between a root path and each path. Rules in deeper directories take precedence over rules closer to the root, and
+/// within a directory, rules from files later in the list of file names take precedence
+pub struct IgnoreFiles {
+    root_path: PathBuf,
+    file_names: Vec<&'static str>,
+    matchers: HashMap<PathBuf, Option<Gitignore>>,
+}
+
+impl IgnoreFiles {
+    pub fn new(root_path: &Path, file_names: Vec<&'static str>) -> Self {
+        IgnoreFiles {
+            root_path: root_path.to_path_buf(),
+            file_names,
+            matchers: HashMap::new(),
+        }
+    }
+
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool) -> bool {
+        for directory in path.ancestors().skip(1) {
+            if let Some(matcher) = self.matcher(directory) {
+                let path_match = matcher.matched(path, is_dir);
+
+                if path_match.is_ignore() {
+                    return true;
+                }
+
+                if path_match.is_whitelist() {
+                    return false;
+                }
+            }
+
+            if directory == self.root_path {
+                break;
+            }
+        }
+
+        false
+    }
+
+    /// Builds, caches and returns the combined rules of the ignore files in a directory, if it has any
+    fn matcher(&mut self, directory: &Path) -> Option<&Gitignore> {
+        let file_names = &self.file_names;
+
+        self.matchers
+            .entry(directory.to_path_buf())
+            .or_insert_with(|| {
+                let mut builder = GitignoreBuilder::new(directory);
+                let mut has_ignore_file = false;
+
+                for file_name in file_names {
+                    let ignore_file_path = directory.join(file_name);
+
+                    if ignore_file_path.is_file() {
+                        has_ignore_file |= builder.add(ignore_file_path).is_none();
+                    }
+                }
+
+                if has_ignore_file {
+                    builder.build().ok()
+                } else {
+                    None
+                }
+            })
+            .as_ref()
+    }
+}
+
+#[test]
+fn ignore_paths_matched_by_nested_gitignore_files() {
+    use std::fs;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    fs::create_dir_all(root_path.join("target")).unwrap();
+    fs::create_dir_all(root_path.join("src/generated")).unwrap();
+    fs::write(root_path.join(".gitignore"), "target/\n*.log\n").unwrap();
+    fs::write(root_path.join("src/.gitignore"), "generated/\n!keep.log\n").unwrap();
+
+    let mut ignore_files = IgnoreFiles::new(root_path, vec![".gitignore"]);
+
+    assert!(ignore_files.is_ignored(&root_path.join("target"), true));
+    assert!(ignore_files.is_ignored(&root_path.join("debug.log"), false));
+    assert!(ignore_files.is_ignored(&root_path.join("src/generated"), true));
+    assert!(ignore_files.is_ignored(&root_path.join("src/debug.log"), false));
+    assert!(!ignore_files.is_ignored(&root_path.join("src/keep.log"), false));
+    assert!(!ignore_files.is_ignored(&root_path.join("src/main.rs"), false));
+    assert!(!ignore_files.is_ignored(&root_path.join("generated"), true));
+}
+
+#[test]
+fn linemanignore_rules_take_precedence_over_gitignore_rules() {
+    use std::fs;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    fs::create_dir_all(root_path.join("vendor")).unwrap();
+    fs::write(root_path.join(".gitignore"), "*.log\n").unwrap();
+    fs::write(
+        root_path.join(LINEMANIGNORE_FILE_NAME),
+        "fixtures/\n!keep.log\n",
+    )
+    .unwrap();
+    fs::write(root_path.join("vendor/.linemanignore"), "*.c\n").unwrap();
+
+    let mut ignore_files = IgnoreFiles::new(root_path, vec![".gitignore", LINEMANIGNORE_FILE_NAME]);
+
+    assert!(ignore_files.is_ignored(&root_path.join("debug.log"), false));
+    assert!(!ignore_files.is_ignored(&root_path.join("keep.log"), false));
+    assert!(ignore_files.is_ignored(&root_path.join("fixtures"), true));
+    assert!(ignore_files.is_ignored(&root_path.join("vendor/zlib.c"), false));
+    assert!(!ignore_files.is_ignored(&root_path.join("main.c"), false));
+}
@@ -0,0 +1,1263 @@
+//! The file cleaning logic behind lineman, independent of any filesystem access
+
+mod encoding;
+pub mod rules;
+mod stream;
+
+pub use encoding::Encoding;
+pub use stream::{clean_stream, detect_stream_line_ending, is_streamable, StreamStats};
+
+use rules::{
+    CleaningRule, CollapseBlankLines, CollapseLeadingSpaces, ExpandLeadingTabs, Line,
+    NormalizeFinalNewlines, RewriteLineEndings, SingleFinalNewline, StripInvisibleCharacters,
+    StripStrayCarriageReturns, TrimLeadingBlankLines, TrimMarkdownTrailingWhitespace,
+    TrimTrailingWhitespace,
+};
+
+use std::str::FromStr;
+
+/// A style of line terminator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    /// `\n`, used on Unix-like systems
+    Lf,
+    /// `\r\n`, used on Windows
+    Crlf,
+    /// `\r`, used on classic Mac OS
+    Cr,
+}
+
+impl LineEnding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+}
+
+impl FromStr for LineEnding {
+    type Err = String;
+
+    fn from_str(line_ending: &str) -> Result<Self, Self::Err> {
+        match line_ending.to_lowercase().as_str() {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            "cr" => Ok(LineEnding::Cr),
+            _ => Err(format!("Unknown line ending: {}", line_ending)),
+        }
+    }
+}
+
+/// Options that control how lines are cleaned
+#[derive(Debug, Clone)]
+pub struct CleanOptions {
+    /// Removes trailing blank lines and ensures the final line ends with a newline
+    pub normalize_eof_newlines: bool,
+
+    /// Limits EOF newline normalization to removing trailing blank lines, so content that deliberately ends without a
+    /// newline keeps ending without one
+    pub normalize_trailing_blanks_only: bool,
+
+    /// Expands tabs in the leading indentation of each line into spaces
+    pub tabs_to_spaces: bool,
+
+    /// Collapses each full tab width of spaces in the leading indentation of each line into a tab
+    pub spaces_to_tabs: bool,
+
+    /// The number of columns between tab stops used when converting indentation
+    pub tab_width: usize,
+
+    /// The line ending every line is rewritten to, with the input's dominant line ending preserved when unset
+    pub line_ending: Option<LineEnding>,
+
+    /// Removes a UTF-8 byte order mark from the start of the content
+    pub strip_bom: bool,
+
+    /// The number of consecutive blank lines any longer run of blank lines is collapsed to, with runs left alone when
+    /// unset
+    pub max_consecutive_blank_lines: Option<usize>,
+
+    /// Removes blank lines from the start of the content
+    pub trim_leading_blank_lines: bool,
+
+    /// How many blank lines at the end of the content are kept when normalizing EOF newlines
+    pub trailing_blank_lines: usize,
+
+    /// Trims trailing whitespace from each line
+    pub trim_trailing_whitespace: bool,
+
+    /// The characters trimmed from the end of each line, with all Unicode whitespace trimmed when unset
+    pub trim_chars: Option<Vec<char>>,
+
+    /// Leaves the content of the first line, such as a shebang, exactly as it is. Its line ending is still cleaned
+    pub preserve_first_line: bool,
+
+    /// Keeps Markdown hard line breaks when trimming, leaving exactly two trailing spaces on lines with content that
+    /// end in two or more
+    pub markdown_hard_line_breaks: bool,
+
+    /// Guarantees that non-empty content ends in exactly one line ending, even when EOF newline normalization is
+    /// disabled
+    pub single_final_newline: bool,
+
+    /// Removes carriage returns inside lines, leaving only those that are part of a line ending
+    pub strip_stray_cr: bool,
+
+    /// The invisible characters removed from anywhere in a line, such as `rules::INVISIBLE_CHARACTERS`, with none
+    /// removed when unset
+    pub strip_invisible: Option<Vec<char>>,
+}
+
+impl Default for CleanOptions {
+    fn default() -> Self {
+        CleanOptions {
+            normalize_eof_newlines: true,
+            normalize_trailing_blanks_only: false,
+            tabs_to_spaces: false,
+            spaces_to_tabs: false,
+            tab_width: 4,
+            line_ending: None,
+            strip_bom: false,
+            max_consecutive_blank_lines: None,
+            trim_leading_blank_lines: false,
+            trailing_blank_lines: 0,
+            trim_trailing_whitespace: true,
+            trim_chars: None,
+            markdown_hard_line_breaks: false,
+            preserve_first_line: false,
+            single_final_newline: false,
+            strip_stray_cr: false,
+            strip_invisible: None,
+        }
+    }
+}
+
+impl CleanOptions {
+    /// Builds the cleaning rules these options enable, in the order they run
+    pub fn rules(&self, line_ending: LineEnding) -> Vec<Box<dyn CleaningRule>> {
+        let mut rules = self.line_rules(line_ending);
+
+        if self.trim_leading_blank_lines {
+            rules.push(Box::new(TrimLeadingBlankLines));
+        }
+
+        if let Some(max_blank_line_count) = self.max_consecutive_blank_lines {
+            rules.push(Box::new(CollapseBlankLines {
+                max_blank_line_count,
+            }));
+        }
+
+        if self.normalize_eof_newlines {
+            rules.push(Box::new(NormalizeFinalNewlines {
+                line_ending,
+                trailing_blank_lines: self.trailing_blank_lines,
+                add_final_newline: !self.normalize_trailing_blanks_only,
+            }));
+        }
+
+        if self.single_final_newline {
+            rules.push(Box::new(SingleFinalNewline { line_ending }));
+        }
+
+        rules
+    }
+
+    /// Builds the rules that clean each line on its own, without looking at the lines around it
+    pub fn line_rules(&self, line_ending: LineEnding) -> Vec<Box<dyn CleaningRule>> {
+        let mut rules: Vec<Box<dyn CleaningRule>> = Vec::new();
+
+        // Stray carriage returns and invisible characters go first, so that whitespace they were hiding behind is trimmed
+        // as well
+        if self.strip_stray_cr {
+            rules.push(Box::new(StripStrayCarriageReturns));
+        }
+
+        if let Some(invisible_chars) = &self.strip_invisible {
+            rules.push(Box::new(StripInvisibleCharacters {
+                invisible_chars: invisible_chars.clone(),
+            }));
+        }
+
+        if self.trim_trailing_whitespace && self.markdown_hard_line_breaks {
+            rules.push(Box::new(TrimMarkdownTrailingWhitespace {
+                trim_chars: self.trim_chars.clone(),
+            }));
+        } else if self.trim_trailing_whitespace {
+            rules.push(Box::new(TrimTrailingWhitespace {
+                trim_chars: self.trim_chars.clone(),
+            }));
+        }
+
+        if self.tabs_to_spaces {
+            rules.push(Box::new(ExpandLeadingTabs {
+                tab_width: self.tab_width,
+            }));
+        } else if self.spaces_to_tabs {
+            rules.push(Box::new(CollapseLeadingSpaces {
+                tab_width: self.tab_width,
+            }));
+        }
+
+        rules.push(Box::new(RewriteLineEndings { line_ending }));
+
+        rules
+    }
+}
+
+/// Cleans a string in memory, returning the cleaned content
+pub fn clean_string(input: &str, options: &CleanOptions) -> String {
+    let input = if options.strip_bom {
+        strip_bom(input)
+    } else {
+        input
+    };
+    let lines = split_lines(input);
+    let (clean_lines, _) = clean_lines(&lines, options);
+
+    clean_lines.concat()
+}
+
+/// The number of leading bytes inspected when deciding whether content is binary
+const BINARY_DETECTION_LENGTH: usize = 8 * 1024;
+
+/// Guesses whether raw bytes are binary rather than text, by looking for a NUL byte near the start
+pub fn is_binary(bytes: &[u8]) -> bool {
+    bytes
+        .iter()
+        .take(BINARY_DETECTION_LENGTH)
+        .any(|byte| *byte == 0)
+}
+
+/// Removes the UTF-8 byte order mark from the very start of a string, if there is one
+pub fn strip_bom(input: &str) -> &str {
+    input.strip_prefix('\u{feff}').unwrap_or(input)
+}
+
+/// Finds the most common line ending in a string, returning `None` if it contains no line endings at all. Ties favor
+/// LF, then CRLF
+pub fn detect_line_ending(input: &str) -> Option<LineEnding> {
+    let crlf_count = input.matches("\r\n").count();
+    let lf_count = input.matches('\n').count() - crlf_count;
+    let cr_count = input.matches('\r').count() - crlf_count;
+
+    dominant_line_ending(lf_count, crlf_count, cr_count)
+}
+
+/// Whether content ends its lines in more than one style. The raw bytes are counted, so any encoding that keeps
+/// line endings as single bytes can be checked before it's decoded
+pub fn has_mixed_line_endings(input: &[u8]) -> bool {
+    let crlf_count = input.windows(2).filter(|pair| *pair == b"\r\n").count();
+    let lf_count = input.iter().filter(|byte| **byte == b'\n').count() - crlf_count;
+    let cr_count = input.iter().filter(|byte| **byte == b'\r').count() - crlf_count;
+
+    is_mixed(lf_count, crlf_count, cr_count)
+}
+
+/// Whether more than one line ending was counted
+fn is_mixed(lf_count: usize, crlf_count: usize, cr_count: usize) -> bool {
+    [lf_count, crlf_count, cr_count]
+        .into_iter()
+        .filter(|count| *count > 0)
+        .count()
+        > 1
+}
+
+/// Picks the line ending with the highest count, with ties favoring LF, then CRLF
+fn dominant_line_ending(lf_count: usize, crlf_count: usize, cr_count: usize) -> Option<LineEnding> {
+    [
+        (LineEnding::Cr, cr_count),
+        (LineEnding::Crlf, crlf_count),
+        (LineEnding::Lf, lf_count),
+    ]
+    .into_iter()
+    .filter(|(_, count)| *count > 0)
+    .max_by_key(|(_, count)| *count)
+    .map(|(line_ending, _)| line_ending)
+}
+
+/// Splits a string into lines, keeping each line's terminator. A lone `\r` only ends a line in content that is
+/// mostly CR-terminated, so that each of its lines can be cleaned individually
+pub fn split_lines(input: &str) -> Vec<&str> {
+    if detect_line_ending(input) != Some(LineEnding::Cr) {
+        return input.split_inclusive('\n').collect();
+    }
+
+    let bytes = input.as_bytes();
+    let mut lines = Vec::new();
+    let mut line_start = 0;
+
+    for (index, byte) in bytes.iter().enumerate() {
+        let is_line_end =
+            *byte == b'\n' || (*byte == b'\r' && bytes.get(index + 1) != Some(&b'\n'));
+
+        if is_line_end {
+            lines.push(&input[line_start..=index]);
+            line_start = index + 1;
+        }
+    }
+
+    if line_start < input.len() {
+        lines.push(&input[line_start..]);
+    }
+
+    lines
+}
+
+/// A line longer than the maximum line length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LongLine {
+    /// The 1-based number of the line
+    pub line_number: usize,
+    /// The number of characters in the line, not counting its line ending
+    pub length: usize,
+}
+
+/// Finds the lines with more characters (Unicode scalar values) than the maximum, not counting their line endings
+pub fn find_long_lines(lines: &[String], max_line_length: usize) -> Vec<LongLine> {
+    lines
+        .iter()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let length = Line::parse(line).content.chars().count();
+
+            (length > max_line_length).then_some(LongLine {
+                line_number: index + 1,
+                length,
+            })
+        })
+        .collect()
+}
+
+/// Whether content was missing the newline at the end of its last line, and cleaning added it
+pub fn adds_final_newline(lines: &[&str], clean_lines: &[String]) -> bool {
+    let ends_with_newline = |line: &str| line.ends_with(['\n', '\r']);
+
+    lines.last().is_some_and(|line| !ends_with_newline(line))
+        && clean_lines
+            .last()
+            .is_some_and(|line| ends_with_newline(line))
+}
+
+/// Counts the lines that differ between the original and cleaned content, including lines that were removed
+pub fn count_changed_lines(lines: &[&str], clean_lines: &[String]) -> usize {
+    let differing_line_count = lines
+        .iter()
+        .zip(clean_lines)
+        .filter(|(line, clean_line)| *line != clean_line)
+        .count();
+
+    differing_line_count + lines.len().abs_diff(clean_lines.len())
+}
+
+/// Cleans each line, returning the cleaned lines and whether any of them changed. Empty content has no lines, so it
+/// always stays empty, without a newline being added to it. Content made up only of blank and whitespace-only lines,
+/// including a lone newline, is all trailing blank lines, so normalizing EOF newlines empties it as well
+pub fn clean_lines(lines: &[&str], options: &CleanOptions) -> (Vec<String>, bool) {
+    let first_line = preserved_first_line(lines, options);
+    let cleaned_lines = options
+        .rules(resolve_line_ending(lines, options))
+        .iter()
+        .fold(parse_numbered_lines(lines), |lines, rule| {
+            restore_first_line(rule.apply(lines), first_line.as_deref())
+        })
+        .into_iter()
+        .map(Line::into_string)
+        .collect::<Vec<_>>();
+
+    // This is probably slow, maybe a better method can be implemented later
+    let lines_were_cleaned = lines != cleaned_lines;
+
+    (cleaned_lines, lines_were_cleaned)
+}
+
+/// An issue that cleaning fixes, found on a line of the original content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    /// The 1-based number of the line
+    pub line_number: usize,
+    /// The id of the rule that found the issue
+    pub rule_id: &'static str,
+    /// What is wrong with the line, as described by the rule that fixes it
+    pub message: &'static str,
+}
+
+/// Runs the same rules as `clean_lines`, reporting every line that each rule changes or removes instead of the
+/// cleaned lines. Issues are ordered by line number, then by the order the rules run in
+pub fn find_issues(lines: &[&str], options: &CleanOptions) -> Vec<Issue> {
+    let mut issues = Vec::new();
+    let first_line = preserved_first_line(lines, options);
+    let mut current_lines = parse_numbered_lines(lines);
+
+    for rule in options.rules(resolve_line_ending(lines, options)) {
+        let previous_lines = current_lines.clone();
+        current_lines = restore_first_line(rule.apply(current_lines), first_line.as_deref());
+
+        // Rules never reorder or add lines, so the lines that are left can be matched up with the previous ones in a
+        // single pass
+        let mut remaining_lines = current_lines.iter().peekable();
+
+        for previous_line in &previous_lines {
+            let is_changed = match remaining_lines.peek() {
+                Some(line) if line.line_number == previous_line.line_number => {
+                    remaining_lines.next() != Some(previous_line)
+                }
+                _ => true,
+            };
+
+            if is_changed {
+                issues.push(Issue {
+                    line_number: previous_line.line_number,
+                    rule_id: rule.id(),
+                    message: rule.issue(),
+                });
+            }
+        }
+    }
+
+    // The sort is stable, so the issues on each line stay in rule order
+    issues.sort_by_key(|issue| issue.line_number);
+    issues
+}
+
+/// The line ending the rules rewrite lines to, which is the one in the options or else the most common one
+fn resolve_line_ending(lines: &[&str], options: &CleanOptions) -> LineEnding {
+    options
+        .line_ending
+        .or_else(|| detect_line_ending(&lines.concat()))
+        .unwrap_or(LineEnding::Lf)
+}
+
+/// The content of the first line, when the options preserve it
+fn preserved_first_line(lines: &[&str], options: &CleanOptions) -> Option<String> {
+    lines
+        .first()
+        .filter(|_| options.preserve_first_line)
+        .map(|first_line| Line::parse(first_line).content)
+}
+
+/// Puts back the original content of the first line after a rule has run, unless the rule removed it. Restoring it
+/// after every rule keeps later rules, such as those that remove blank lines, from seeing what earlier ones did to it
+pub(crate) fn restore_first_line(mut lines: Vec<Line>, first_line: Option<&str>) -> Vec<Line> {
+    if let (Some(first_line), Some(line)) = (first_line, lines.first_mut()) {
+        if line.line_number == 1 {
+            line.content = first_line.to_string();
+        }
+    }
+
+    lines
+}
+
+fn parse_numbered_lines(lines: &[&str]) -> Vec<Line> {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(index, line)| Line::parse_numbered(line, index + 1))
+        .collect()
+}
+
+#[test]
+fn clean_lines_with_trailing_spaces() {
+    let input_lines = [
+        "def main():   \n",
+        "    print(\"Hello World\")    \n",
+        "    \n",
+        "if __name__ == \"__main__\":    \n",
+        "    main()    \n",
+    ];
+
+    let expected_output_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()\n",
+    ];
+
+    let (output_lines, lines_have_changes) = clean_lines(&input_lines, &CleanOptions::default());
+
+    assert_eq!(expected_output_lines.to_vec(), output_lines);
+    assert!(lines_have_changes);
+    assert_eq!(count_changed_lines(&input_lines, &output_lines), 5);
+}
+
+#[test]
+fn clean_lines_with_trailing_tabs() {
+    let input_lines = [
+        "def main():\t\n",
+        "    print(\"Hello World\")\t\n",
+        "\t\n",
+        "if __name__ == \"__main__\":\t\n",
+        "    main()\t\n",
+    ];
+
+    let expected_output_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()\n",
+    ];
+
+    let (output_lines, lines_have_changes) = clean_lines(&input_lines, &CleanOptions::default());
+
+    assert_eq!(expected_output_lines.to_vec(), output_lines);
+    assert!(lines_have_changes);
+    assert_eq!(count_changed_lines(&input_lines, &output_lines), 5);
+}
+
+#[test]
+fn add_newline_to_end_of_file() {
+    let input_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()",
+    ];
+
+    let expected_output_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()\n",
+    ];
+
+    let (output_lines, lines_have_changes) = clean_lines(&input_lines, &CleanOptions::default());
+
+    assert_eq!(expected_output_lines.to_vec(), output_lines);
+    assert!(lines_have_changes);
+    assert_eq!(count_changed_lines(&input_lines, &output_lines), 1);
+    assert!(adds_final_newline(&input_lines, &output_lines));
+    assert!(!adds_final_newline(&expected_output_lines, &output_lines));
+}
+
+#[test]
+fn do_not_add_newline_to_end_of_file() {
+    let input_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()",
+    ];
+
+    let expected_output_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()",
+    ];
+
+    let (output_lines, lines_have_changes) = clean_lines(
+        &input_lines,
+        &CleanOptions {
+            normalize_eof_newlines: false,
+            ..CleanOptions::default()
+        },
+    );
+
+    assert_eq!(expected_output_lines.to_vec(), output_lines);
+    assert!(!lines_have_changes);
+    assert_eq!(count_changed_lines(&input_lines, &output_lines), 0);
+    assert!(!adds_final_newline(&input_lines, &output_lines));
+}
+
+#[test]
+fn trim_final_line_without_newline_when_not_normalizing() {
+    let input_lines = ["if __name__ == \"__main__\":\n", "    main()   "];
+    let expected_output_lines = ["if __name__ == \"__main__\":\n", "    main()"];
+
+    let (output_lines, lines_have_changes) = clean_lines(
+        &input_lines,
+        &CleanOptions {
+            normalize_eof_newlines: false,
+            ..CleanOptions::default()
+        },
+    );
+
+    assert_eq!(expected_output_lines.to_vec(), output_lines);
+    assert!(lines_have_changes);
+    assert_eq!(count_changed_lines(&input_lines, &output_lines), 1);
+}
+
+#[test]
+fn remove_excessive_newlines_from_end_of_file() {
+    let input_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()\n",
+        "\n",
+        "\n",
+        "\n",
+    ];
+
+    let expected_output_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()\n",
+    ];
+
+    let (output_lines, lines_have_changes) = clean_lines(&input_lines, &CleanOptions::default());
+
+    assert_eq!(expected_output_lines.to_vec(), output_lines);
+    assert!(lines_have_changes);
+    assert_eq!(count_changed_lines(&input_lines, &output_lines), 3);
+}
+
+#[test]
+fn only_remove_trailing_blank_lines_when_asked() {
+    let options = CleanOptions {
+        normalize_trailing_blanks_only: true,
+        ..CleanOptions::default()
+    };
+    let excessive_newline_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()\n",
+        "\n",
+        "\n",
+        "\n",
+    ];
+    let missing_newline_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()",
+    ];
+
+    let (output_lines, lines_have_changes) = clean_lines(&excessive_newline_lines, &options);
+
+    assert_eq!(excessive_newline_lines[..5].to_vec(), output_lines);
+    assert!(lines_have_changes);
+
+    let (output_lines, lines_have_changes) = clean_lines(&missing_newline_lines, &options);
+
+    assert_eq!(missing_newline_lines.to_vec(), output_lines);
+    assert!(!lines_have_changes);
+    assert!(!adds_final_newline(&missing_newline_lines, &output_lines));
+    assert_eq!(clean_string("last  \n\n  ", &options), "last\n");
+}
+
+#[test]
+fn keep_one_trailing_blank_line_at_end_of_file() {
+    let input_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()\n",
+        "\n",
+        "\n",
+        "\n",
+    ];
+
+    let expected_output_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()\n",
+        "\n",
+    ];
+
+    let (output_lines, lines_have_changes) = clean_lines(
+        &input_lines,
+        &CleanOptions {
+            trailing_blank_lines: 1,
+            ..CleanOptions::default()
+        },
+    );
+
+    assert_eq!(expected_output_lines.to_vec(), output_lines);
+    assert!(lines_have_changes);
+    assert_eq!(count_changed_lines(&input_lines, &output_lines), 2);
+}
+
+#[test]
+fn do_not_remove_excessive_newlines_from_end_of_file() {
+    let input_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()\n",
+        "\n",
+        "\n",
+        "\n",
+    ];
+
+    let expected_output_lines = [
+        "def main():\n",
+        "    print(\"Hello World\")\n",
+        "\n",
+        "if __name__ == \"__main__\":\n",
+        "    main()\n",
+        "\n",
+        "\n",
+        "\n",
+    ];
+
+    let (output_lines, lines_have_changes) = clean_lines(
+        &input_lines,
+        &CleanOptions {
+            normalize_eof_newlines: false,
+            ..CleanOptions::default()
+        },
+    );
+
+    assert_eq!(expected_output_lines.to_vec(), output_lines);
+    assert!(!lines_have_changes);
+    assert_eq!(count_changed_lines(&input_lines, &output_lines), 0);
+}
+
+#[test]
+fn clean_string_with_trailing_whitespace() {
+    let input = "def main():   \n    print(\"Hello World\")\t\n\n\n";
+    let expected_output = "def main():\n    print(\"Hello World\")\n";
+
+    assert_eq!(
+        expected_output,
+        clean_string(input, &CleanOptions::default())
+    );
+}
+
+#[test]
+fn convert_leading_tabs_to_spaces() {
+    let input_lines = [
+        "def main():\n",
+        "\tprint(\"Hello\\tWorld\")\n",
+        "\tif True:\n",
+        "\t\tmain()\t# recurse\n",
+    ];
+
+    let expected_output_lines = [
+        "def main():\n",
+        "    print(\"Hello\\tWorld\")\n",
+        "    if True:\n",
+        "        main()\t# recurse\n",
+    ];
+
+    let (output_lines, lines_have_changes) = clean_lines(
+        &input_lines,
+        &CleanOptions {
+            tabs_to_spaces: true,
+            ..CleanOptions::default()
+        },
+    );
+
+    assert_eq!(expected_output_lines.to_vec(), output_lines);
+    assert!(lines_have_changes);
+}
+
+#[test]
+fn convert_mixed_tab_and_space_indentation_to_spaces() {
+    let input_lines = ["  \tfoo()\n", "\t  bar()\n", " \t \tbaz()\n"];
+
+    let expected_output_lines = ["    foo()\n", "      bar()\n", "        baz()\n"];
+
+    let (output_lines, lines_have_changes) = clean_lines(
+        &input_lines,
+        &CleanOptions {
+            tabs_to_spaces: true,
+            ..CleanOptions::default()
+        },
+    );
+
+    assert_eq!(expected_output_lines.to_vec(), output_lines);
+    assert!(lines_have_changes);
+}
+
+#[test]
+fn convert_leading_tabs_to_spaces_with_custom_tab_width() {
+    let input_lines = ["\tfoo()\n", "\t\tbar()\n"];
+
+    let expected_output_lines = ["  foo()\n", "    bar()\n"];
+
+    let (output_lines, lines_have_changes) = clean_lines(
+        &input_lines,
+        &CleanOptions {
+            tabs_to_spaces: true,
+            tab_width: 2,
+            ..CleanOptions::default()
+        },
+    );
+
+    assert_eq!(expected_output_lines.to_vec(), output_lines);
+    assert!(lines_have_changes);
+}
+
+#[test]
+fn convert_leading_spaces_to_tabs() {
+    let input_lines = [
+        "    foo()\n",
+        "        bar()\n",
+        "      baz()\n",
+        "\t    qux()  \n",
+    ];
+
+    let expected_output_lines = ["\tfoo()\n", "\t\tbar()\n", "\t  baz()\n", "\t\tqux()\n"];
+
+    let (output_lines, lines_have_changes) = clean_lines(
+        &input_lines,
+        &CleanOptions {
+            spaces_to_tabs: true,
+            ..CleanOptions::default()
+        },
+    );
+
+    assert_eq!(expected_output_lines.to_vec(), output_lines);
+    assert!(lines_have_changes);
+}
+
+#[test]
+fn convert_crlf_line_endings_to_lf() {
+    let input = "def main():\r\n    print(\"Hello World\")  \r\n\r\n";
+    let expected_output = "def main():\n    print(\"Hello World\")\n";
+
+    let options = CleanOptions {
+        line_ending: Some(LineEnding::Lf),
+        ..CleanOptions::default()
+    };
+
+    assert_eq!(expected_output, clean_string(input, &options));
+}
+
+#[test]
+fn convert_cr_line_endings_to_lf() {
+    let input = "def main():\r    print(\"Hello World\")  \r";
+    let expected_output = "def main():\n    print(\"Hello World\")\n";
+
+    let options = CleanOptions {
+        line_ending: Some(LineEnding::Lf),
+        ..CleanOptions::default()
+    };
+
+    assert_eq!(expected_output, clean_string(input, &options));
+}
+
+#[test]
+fn convert_lf_line_endings_to_cr() {
+    let input = "10 PRINT \"HELLO\"  \n20 GOTO 10\n";
+    let expected_output = "10 PRINT \"HELLO\"\r20 GOTO 10\r";
+
+    let options = CleanOptions {
+        line_ending: Some(LineEnding::Cr),
+        ..CleanOptions::default()
+    };
+
+    assert_eq!(expected_output, clean_string(input, &options));
+    assert_eq!("cr".parse(), Ok(LineEnding::Cr));
+}
+
+#[test]
+fn convert_mixed_line_endings_to_crlf() {
+    let input = "@echo off  \r\nset NAME=World\t\necho Hello %NAME%\n\r\n";
+    let expected_output = "@echo off\r\nset NAME=World\r\necho Hello %NAME%\r\n";
+
+    let options = CleanOptions {
+        line_ending: Some(LineEnding::Crlf),
+        ..CleanOptions::default()
+    };
+
+    assert_eq!(expected_output, clean_string(input, &options));
+}
+
+#[test]
+fn keep_crlf_files_unchanged_when_converting_to_crlf() {
+    let input_lines = ["@echo off\r\n", "echo Hello World\r\n"];
+
+    let (output_lines, lines_have_changes) = clean_lines(
+        &input_lines,
+        &CleanOptions {
+            line_ending: Some(LineEnding::Crlf),
+            ..CleanOptions::default()
+        },
+    );
+
+    assert_eq!(input_lines.to_vec(), output_lines);
+    assert!(!lines_have_changes);
+}
+
+#[test]
+fn preserve_crlf_line_endings_while_trimming() {
+    let input_lines = [
+        "def main():   \r\n",
+        "    print(\"Hello World\")\t\r\n",
+        "\r\n",
+        "if __name__ == \"__main__\":\r\n",
+        "    main()  ",
+    ];
+
+    let expected_output_lines = [
+        "def main():\r\n",
+        "    print(\"Hello World\")\r\n",
+        "\r\n",
+        "if __name__ == \"__main__\":\r\n",
+        "    main()\r\n",
+    ];
+
+    let (output_lines, lines_have_changes) = clean_lines(&input_lines, &CleanOptions::default());
+
+    assert_eq!(expected_output_lines.to_vec(), output_lines);
+    assert!(lines_have_changes);
+}
+
+#[test]
+fn preserve_cr_line_endings_while_trimming() {
+    let input = "def main():  \r    print(\"Hello World\")\r\r";
+    let expected_output = "def main():\r    print(\"Hello World\")\r";
+
+    assert_eq!(
+        expected_output,
+        clean_string(input, &CleanOptions::default())
+    );
+}
+
+#[test]
+fn detect_dominant_line_ending() {
+    assert_eq!(detect_line_ending("a\nb\nc\r\n"), Some(LineEnding::Lf));
+    assert_eq!(detect_line_ending("a\r\nb\r\nc\n"), Some(LineEnding::Crlf));
+    assert_eq!(detect_line_ending("a\rb\rc\n"), Some(LineEnding::Cr));
+    assert_eq!(detect_line_ending("a\nb\r\n"), Some(LineEnding::Lf));
+    assert_eq!(detect_line_ending("abc"), None);
+}
+
+#[test]
+fn detect_mixed_line_endings() {
+    assert!(has_mixed_line_endings(b"a\r\nb\nc\r\n"));
+    assert!(has_mixed_line_endings(b"a\rb\r\n"));
+    assert!(!has_mixed_line_endings(b"a\r\nb\r\n"));
+    assert!(!has_mixed_line_endings(b"a\nb"));
+    assert!(!has_mixed_line_endings(b""));
+}
+
+#[test]
+fn strip_leading_bom() {
+    let options = CleanOptions {
+        strip_bom: true,
+        ..CleanOptions::default()
+    };
+
+    assert_eq!(
+        "fn main() {}\n",
+        clean_string("\u{feff}fn main() {}\n", &options)
+    );
+    assert_eq!("fn main() {}\n", clean_string("fn main() {}\n", &options));
+    assert_eq!(
+        "let bom = '\u{feff}';\n",
+        clean_string("let bom = '\u{feff}';\n", &options)
+    );
+}
+
+#[test]
+fn detect_binary_content() {
+    assert!(is_binary(b"\x89PNG\r\n\x1a\n\x00\x00\x00\rIHDR"));
+    assert!(!is_binary(b"fn main() {}\n"));
+    assert!(!is_binary(&[[b'a'; 8 * 1024].as_slice(), &[0]].concat()));
+}
+
+#[test]
+fn collapse_consecutive_blank_lines_in_the_middle_of_the_file() {
+    let options = CleanOptions {
+        max_consecutive_blank_lines: Some(1),
+        ..CleanOptions::default()
+    };
+    let lines = ["first\n", "\n", "  \n", "\t\n", "second\n", "\n", "third\n"];
+    let (cleaned_lines, lines_were_cleaned) = clean_lines(&lines, &options);
+
+    assert_eq!(
+        cleaned_lines,
+        vec!["first\n", "\n", "second\n", "\n", "third\n"]
+    );
+    assert!(lines_were_cleaned);
+}
+
+#[test]
+fn remove_every_blank_line_when_the_maximum_is_zero() {
+    let options = CleanOptions {
+        max_consecutive_blank_lines: Some(0),
+        ..CleanOptions::default()
+    };
+    let lines = ["first\n", "\n", "second\n", " \n", "\n", "third\n"];
+    let (cleaned_lines, _) = clean_lines(&lines, &options);
+
+    assert_eq!(cleaned_lines, vec!["first\n", "second\n", "third\n"]);
+}
+
+#[test]
+fn keep_runs_of_blank_lines_within_the_maximum() {
+    let options = CleanOptions {
+        max_consecutive_blank_lines: Some(2),
+        ..CleanOptions::default()
+    };
+    let lines = ["first\n", "\n", "\n", "second\n"];
+    let (cleaned_lines, lines_were_cleaned) = clean_lines(&lines, &options);
+
+    assert_eq!(cleaned_lines, lines);
+    assert!(!lines_were_cleaned);
+}
+
+#[test]
+fn trim_leading_blank_lines() {
+    let options = CleanOptions {
+        trim_leading_blank_lines: true,
+        ..CleanOptions::default()
+    };
+    let lines = ["\n", "  \n", "\n", "#!/bin/sh\n", "\n", "echo hi\n"];
+    let (cleaned_lines, lines_were_cleaned) = clean_lines(&lines, &options);
+
+    assert_eq!(cleaned_lines, vec!["#!/bin/sh\n", "\n", "echo hi\n"]);
+    assert!(lines_were_cleaned);
+
+    let (cleaned_lines, _) = clean_lines(&lines, &CleanOptions::default());
+
+    assert_eq!(cleaned_lines.len(), lines.len());
+}
+
+#[test]
+fn trim_only_the_given_characters() {
+    let options = CleanOptions {
+        trim_chars: Some(vec![' ', '\t']),
+        ..CleanOptions::default()
+    };
+    let lines = ["form feed\x0c \t\n", "non-breaking\u{a0}\n", "\x0c\n"];
+    let (cleaned_lines, _) = clean_lines(&lines, &options);
+
+    assert_eq!(
+        cleaned_lines,
+        vec!["form feed\x0c\n", "non-breaking\u{a0}\n", "\x0c\n"]
+    );
+
+    let (cleaned_lines, _) = clean_lines(&lines, &CleanOptions::default());
+
+    assert_eq!(cleaned_lines, vec!["form feed\n", "non-breaking\n"]);
+}
+
+#[test]
+fn end_with_exactly_one_final_newline() {
+    let options = CleanOptions {
+        normalize_eof_newlines: false,
+        single_final_newline: true,
+        ..CleanOptions::default()
+    };
+    let clean = |input: &str| clean_string(input, &options);
+
+    assert_eq!(clean("first\nlast"), "first\nlast\n");
+    assert_eq!(clean("first\nlast\n"), "first\nlast\n");
+    assert_eq!(clean("first\nlast\n\n \n\t\n\n"), "first\nlast\n");
+    assert_eq!(clean("first\r\nlast\r\n\r\n"), "first\r\nlast\r\n");
+    assert_eq!(clean("\n\n"), "");
+    assert_eq!(clean(""), "");
+}
+
+#[test]
+fn keep_empty_content_empty() {
+    let option_sets = [
+        CleanOptions::default(),
+        CleanOptions {
+            normalize_eof_newlines: false,
+            ..CleanOptions::default()
+        },
+        CleanOptions {
+            trailing_blank_lines: 1,
+            trim_leading_blank_lines: true,
+            line_ending: Some(LineEnding::Crlf),
+            ..CleanOptions::default()
+        },
+    ];
+
+    for options in &option_sets {
+        assert!(split_lines("").is_empty());
+        assert_eq!(clean_lines(&[], options), (Vec::new(), false));
+        assert_eq!(clean_string("", options), "");
+        assert!(find_issues(&[], options).is_empty());
+    }
+}
+
+#[test]
+fn empty_whitespace_only_content_when_normalizing() {
+    for input in ["\n", "\r\n", "\n\n\n", "   ", "  \n\t\n"] {
+        assert_eq!(clean_string(input, &CleanOptions::default()), "");
+        assert_eq!(
+            clean_string(
+                input,
+                &CleanOptions {
+                    trailing_blank_lines: 1,
+                    ..CleanOptions::default()
+                }
+            ),
+            ""
+        );
+    }
+
+    // Without normalization, only the whitespace on each line is removed
+    let options = CleanOptions {
+        normalize_eof_newlines: false,
+        ..CleanOptions::default()
+    };
+
+    assert_eq!(clean_string("\n", &options), "\n");
+    assert_eq!(clean_string("   ", &options), "");
+    assert_eq!(clean_string("  \n\t\n", &options), "\n\n");
+}
+
+#[test]
+fn find_lines_longer_than_the_maximum() {
+    let lines = [
+        "1234\n",
+        "12345\r\n",
+        "123456\n",
+        "\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}\u{e9}",
+    ]
+    .map(String::from);
+
+    assert_eq!(
+        find_long_lines(&lines, 5),
+        vec![
+            LongLine {
+                line_number: 3,
+                length: 6,
+            },
+            LongLine {
+                line_number: 4,
+                length: 6,
+            },
+        ]
+    );
+    assert!(find_long_lines(&lines, 6).is_empty());
+}
+
+#[test]
+fn find_issues_on_the_lines_each_rule_changes() {
+    let lines = split_lines("first  \r\nsecond\n\tthird\n\n\n");
+    let options = CleanOptions {
+        tabs_to_spaces: true,
+        ..CleanOptions::default()
+    };
+
+    assert_eq!(
+        find_issues(&lines, &options),
+        vec![
+            Issue {
+                line_number: 1,
+                rule_id: "trim-trailing-whitespace",
+                message: "Trailing whitespace",
+            },
+            Issue {
+                line_number: 1,
+                rule_id: "rewrite-line-endings",
+                message: "Inconsistent line ending",
+            },
+            Issue {
+                line_number: 3,
+                rule_id: "expand-leading-tabs",
+                message: "Indented with tabs",
+            },
+            Issue {
+                line_number: 4,
+                rule_id: "normalize-final-newlines",
+                message: "Missing final newline or trailing blank line",
+            },
+            Issue {
+                line_number: 5,
+                rule_id: "normalize-final-newlines",
+                message: "Missing final newline or trailing blank line",
+            },
+        ]
+    );
+    assert!(find_issues(&split_lines("clean\n"), &options).is_empty());
+}
+
+#[test]
+fn strip_stray_carriage_returns() {
+    let options = CleanOptions {
+        strip_stray_cr: true,
+        ..CleanOptions::default()
+    };
+
+    assert_eq!(clean_string("foo\rbar\n", &options), "foobar\n");
+    assert_eq!(clean_string("foo \r\r\n", &options), "foo\r\n");
+    assert_eq!(
+        clean_string("foo\rbar\n", &CleanOptions::default()),
+        "foo\rbar\n"
+    );
+}
+
+#[test]
+fn strip_invisible_characters_before_trimming() {
+    let options = CleanOptions {
+        strip_invisible: Some(rules::INVISIBLE_CHARACTERS.to_vec()),
+        ..CleanOptions::default()
+    };
+
+    assert_eq!(
+        clean_string("let\u{200b} x = 1; \u{200d}\n", &options),
+        "let x = 1;\n"
+    );
+}
+
+#[test]
+fn keep_trailing_whitespace_when_trimming_is_disabled() {
+    let options = CleanOptions {
+        trim_trailing_whitespace: false,
+        ..CleanOptions::default()
+    };
+
+    assert_eq!(
+        clean_string("first line  \n\tsecond\t\n\n\n", &options),
+        "first line  \n\tsecond\t\n"
+    );
+    assert_eq!(clean_string("no newline  ", &options), "no newline  \n");
+}
+
+#[test]
+fn keep_markdown_hard_line_breaks_when_cleaning() {
+    let options = CleanOptions {
+        markdown_hard_line_breaks: true,
+        ..CleanOptions::default()
+    };
+
+    assert_eq!(
+        clean_string(
+            "# Title \n\nfirst  \nsecond   \nthird    \n  \nlast  \n\n",
+            &options
+        ),
+        "# Title\n\nfirst  \nsecond  \nthird  \n\nlast  \n"
+    );
+}
+
+#[test]
+fn preserve_the_first_line() {
+    let input = "#!/usr/bin/env python\t \n\tprint(\"hi\")  \r\n\n";
+    let options = CleanOptions {
+        preserve_first_line: true,
+        tabs_to_spaces: true,
+        strip_invisible: Some(rules::INVISIBLE_CHARACTERS.to_vec()),
+        line_ending: Some(LineEnding::Crlf),
+        ..CleanOptions::default()
+    };
+
+    assert_eq!(
+        clean_string(input, &options),
+        "#!/usr/bin/env python\t \r\n    print(\"hi\")\r\n"
+    );
+
+    // A first line that only holds whitespace isn't blank, so it is kept along with a missing final newline
+    assert_eq!(clean_string("  ", &options), "  \r\n");
+
+    let issues = find_issues(&split_lines(input), &options);
+    assert!(issues
+        .iter()
+        .all(|issue| issue.line_number != 1 || issue.rule_id == "rewrite-line-endings"));
+}
@@ -1,319 +1,3098 @@
+mod cache;
+mod config;
+mod editorconfig;
+mod ignore_files;
+mod report;
+mod watch;
+
+use cache::Cache;
+use config::Config;
+use editorconfig::editorconfig_clean_options;
+use filetime::FileTime;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use ignore_files::{IgnoreFiles, LINEMANIGNORE_FILE_NAME};
+use indicatif::{ProgressBar, ProgressStyle};
+use lineman::rules::INVISIBLE_CHARACTERS;
+use lineman::{
+    adds_final_newline, clean_lines, clean_stream, clean_string, count_changed_lines,
+    detect_stream_line_ending, find_issues, find_long_lines, has_mixed_line_endings, is_binary,
+    is_streamable, split_lines, strip_bom, CleanOptions, Encoding, Issue, LineEnding, LongLine,
+    StreamStats,
+};
+use rayon::prelude::*;
+use rayon::ThreadPoolBuilder;
+use regex::RegexSet;
+use report::{CleanStats, ColorChoice, FileOutcome, Report, ReportFormat};
+use similar::TextDiff;
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::ffi::OsStr;
+use std::fmt;
 use std::fs::{self, File};
-use std::io::prelude::*;
-use std::path::{Path, PathBuf};
+use std::io::{self, prelude::*, BufReader, BufWriter, IsTerminal};
+use std::mem;
+use std::path::{Component, Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use structopt::clap::{ErrorKind, Shell};
 use structopt::StructOpt;
-use walkdir::{Error, WalkDir};
+use walkdir::WalkDir;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = "lineman")]
 struct LinemanArgs {
-    /// The root path from which to begin processing
-    #[structopt(short, long)]
-    path: PathBuf,
+    /// The root paths from which to begin processing, each of which is either a directory to walk or a single file to
+    /// clean
+    #[structopt(short, long = "path", required_unless_one = &["stdin-paths", "stdin", "generate-completions"])]
+    paths: Vec<PathBuf>,
+
+    /// Reads newline-separated paths of files to clean from stdin instead of walking the root paths
+    #[structopt(long, conflicts_with = "paths")]
+    stdin_paths: bool,
+
+    /// Cleans content read from stdin and writes it to stdout, without touching any files or printing a report
+    #[structopt(long, conflicts_with_all = &["paths", "stdin-paths"])]
+    stdin: bool,
 
-    /// A list of file extensions that dictates which files are processed
+    /// The file name that content read with `stdin` is cleaned as, so that `markdown` and `editorconfig` apply the
+    /// rules for its extension and location. Nothing is ever read from or written to it
+    #[structopt(long, requires = "stdin")]
+    stdin_filename: Option<PathBuf>,
+
+    /// The configuration file to read settings from, which defaults to `lineman.toml` in the working directory if it
+    /// exists
+    #[structopt(long)]
+    config: Option<PathBuf>,
+
+    /// A list of file extensions that dictates which files are processed. Each value can hold several extensions
+    /// separated by commas, such as "rs,py,md", and a leading dot is ignored
     #[structopt(short, long)]
     extensions: Option<Vec<String>>,
 
     /// Disables EOF newline normalization
     #[structopt(short, long)]
     disable_eof_newline_normalization: bool,
+
+    /// Limits EOF newline normalization to removing trailing blank lines, without adding a newline to files that
+    /// end without one
+    #[structopt(long, conflicts_with_all = &["disable-eof-newline-normalization", "single-final-newline"])]
+    normalize_trailing_blanks_only: bool,
+
+    /// Disables trimming trailing whitespace, so that only line endings and final newlines are cleaned
+    #[structopt(long)]
+    disable_trailing_whitespace_trim: bool,
+
+    /// Keeps Markdown hard line breaks in files with an md or markdown extension: lines with content that end in two
+    /// or more spaces keep exactly two
+    #[structopt(long)]
+    markdown: bool,
+
+    /// Leaves the content of the first line of each file, such as a shebang, exactly as it is. Only its line ending
+    /// is cleaned
+    #[structopt(long)]
+    preserve_first_line: bool,
+
+    /// Guarantees each non-empty file ends in exactly one newline, even when EOF newline normalization is disabled
+    #[structopt(long)]
+    single_final_newline: bool,
+
+    /// Removes carriage returns that aren't part of a CRLF line ending, such as ones left in the middle of a line
+    #[structopt(long)]
+    strip_stray_cr: bool,
+
+    /// Removes zero width and other invisible characters from anywhere in each line: U+200B, U+200C, U+200D, U+2060
+    /// and U+FEFF
+    #[structopt(long)]
+    strip_invisible: bool,
+
+    /// The characters removed by strip-invisible, instead of the default set
+    #[structopt(long, requires = "strip-invisible")]
+    invisible_chars: Option<String>,
+
+    /// Reports files that would be cleaned without modifying them, exiting with a nonzero status if any are found
+    #[structopt(long)]
+    check: bool,
+
+    /// Prints which files would be cleaned, and how many lines in each would change, without modifying them
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Analyzes every selected file without modifying any of them, printing only the totals for the run instead of
+    /// the report
+    #[structopt(long, conflicts_with_all = &["stdin", "list", "print0", "interactive", "watch", "diff"])]
+    stats_only: bool,
+
+    /// Prints a compact JSON summary of the run on its own line after the report, whatever its format and even when
+    /// quiet, for tools that scrape CI logs
+    #[structopt(long, conflicts_with_all = &["stdin", "list", "print0"])]
+    summary_json: bool,
+
+    /// Prints the paths of the files selected for cleaning, one per line, without reading or cleaning any of them
+    #[structopt(long, conflicts_with_all = &["stdin", "watch", "interactive"])]
+    list: bool,
+
+    /// Prints paths terminated by NUL bytes instead of newlines, for `xargs -0`. Along with list, this applies to the
+    /// selected files. Otherwise, the report is replaced by the paths of the files that were, or would be, cleaned
+    #[structopt(long, conflicts_with_all = &["stdin", "watch"])]
+    print0: bool,
+
+    /// Asks before writing each file that would change, answering y (yes), n (no), a (all remaining files) or q
+    /// (quit). Ignored with a warning when stdin isn't a terminal
+    #[structopt(long, conflicts_with_all = &["check", "dry-run", "stdin", "stdin-paths", "watch"])]
+    interactive: bool,
+
+    /// Stops at the first file that can't be read, written or backed up, exiting with its error instead of
+    /// continuing with the remaining files. Files cleaned before the failure stay cleaned
+    #[structopt(long)]
+    fail_fast: bool,
+
+    /// Prints a unified diff of the changes to each file that would be cleaned during a dry run
+    #[structopt(long, requires = "dry-run")]
+    diff: bool,
+
+    /// The number of unchanged lines shown around each change in a diff
+    #[structopt(long, default_value = "3")]
+    diff_context: usize,
+
+    /// Copies each file to `<name>.bak` before it is rewritten
+    #[structopt(long)]
+    backup: bool,
+
+    /// Restores the original modification time of each file after it is rewritten
+    #[structopt(long)]
+    preserve_mtime: bool,
+
+    /// Cleans read-only files too, which are otherwise skipped. The files are still read-only afterwards
+    #[structopt(long)]
+    chmod_writable: bool,
+
+    /// Flushes each cleaned file to disk before it replaces the original, so that a crash right after the run can't
+    /// lose the cleaned content. This makes large runs considerably slower
+    #[structopt(long)]
+    fsync: bool,
+
+    /// Expands tabs in the leading indentation of each line into spaces
+    #[structopt(long)]
+    tabs_to_spaces: bool,
+
+    /// Collapses each full tab width of spaces in the leading indentation of each line into a tab
+    #[structopt(long, conflicts_with = "tabs-to-spaces")]
+    spaces_to_tabs: bool,
+
+    /// The number of columns between tab stops used when converting indentation
+    #[structopt(long, default_value = "4")]
+    tab_width: usize,
+
+    /// The line ending every line is rewritten to
+    #[structopt(long, possible_values = &["lf", "crlf", "cr"], case_insensitive = true)]
+    line_ending: Option<LineEnding>,
+
+    /// Collapses any run of more blank lines than this down to this many. 0 removes every blank line
+    #[structopt(long = "max-consecutive-blank")]
+    max_consecutive_blank_lines: Option<usize>,
+
+    /// How many blank lines to keep at the end of each file when normalizing EOF newlines
+    #[structopt(long, default_value = "0")]
+    trailing_blank_lines: usize,
+
+    /// Removes blank lines from the start of each file
+    #[structopt(long)]
+    trim_leading_blank_lines: bool,
+
+    /// The characters trimmed from the end of each line, instead of all whitespace, e.g. " \t" to keep trailing form
+    /// feeds and non-breaking spaces
+    #[structopt(long)]
+    trim_chars: Option<String>,
+
+    /// Removes a UTF-8 byte order mark from the start of each file
+    #[structopt(long)]
+    strip_bom: bool,
+
+    /// Processes files that look binary instead of skipping them
+    #[structopt(long)]
+    force: bool,
+
+    /// Cleans files that aren't valid UTF-8 by replacing their invalid bytes with U+FFFD, instead of skipping them. A
+    /// warning is printed for each file this rewrites
+    #[structopt(long)]
+    lossy: bool,
+
+    /// Reports lines longer than this many characters, without changing them
+    #[structopt(long)]
+    max_line_length: Option<usize>,
+
+    /// Skips files larger than this size, e.g. 512KB or 10MB. Units are powers of 1024 and a bare number is in bytes
+    #[structopt(long, parse(try_from_str = parse_file_size))]
+    max_file_size: Option<u64>,
+
+    /// A file that remembers which files were clean at the end of the run, so that the next run with the same cleaning
+    /// options can skip them until their modification time or size changes
+    #[structopt(long)]
+    cache: Option<PathBuf>,
+
+    /// Writes cleaned copies of the selected files under this directory instead of cleaning them in place, mirroring
+    /// the path each file was found at. The original files are never modified
+    #[structopt(
+        long,
+        parse(from_os_str),
+        conflicts_with_all = &["check", "dry-run", "stdin", "interactive", "watch", "backup", "cache"]
+    )]
+    output_dir: Option<PathBuf>,
+
+    /// Walks the directories lineman skips by default: `.git`, `target`, `node_modules` and `__pycache__`
+    #[structopt(long)]
+    no_default_ignore: bool,
+
+    /// A list of directory names that are never walked, in addition to the default ones
+    #[structopt(long)]
+    ignore_dir: Vec<String>,
+
+    /// Walks every path, including those excluded by `.gitignore` files. Paths excluded by `.linemanignore` files are
+    /// still skipped
+    #[structopt(long)]
+    no_ignore: bool,
+
+    /// A list of glob patterns, matched against paths relative to the root path, that selects files for processing
+    /// in addition to the extension list
+    #[structopt(short, long)]
+    include: Vec<String>,
+
+    /// A list of glob patterns, matched against paths relative to the root path, that excludes files from
+    /// processing even when they are otherwise selected
+    #[structopt(long)]
+    exclude: Vec<String>,
+
+    /// A list of regular expressions, matched against full paths, that selects files for processing in addition to
+    /// the extension list
+    #[structopt(long)]
+    include_regex: Vec<String>,
+
+    /// A list of regular expressions, matched against full paths, that excludes files from processing even when they
+    /// are otherwise selected
+    #[structopt(long)]
+    exclude_regex: Vec<String>,
+
+    /// Matches file extensions case-insensitively
+    #[structopt(long)]
+    ignore_extension_case: bool,
+
+    /// A list of exact file names (such as `Makefile`) that selects files for processing in addition to the
+    /// extension list
+    #[structopt(long)]
+    filenames: Vec<String>,
+
+    /// Selects files without an extension, such as scripts and config files, in addition to the extension list.
+    /// Binary files among them are still skipped unless force is passed
+    #[structopt(long)]
+    include_no_extension: bool,
+
+    /// The maximum number of directory levels to descend, where 1 processes only the files directly in each root
+    /// path. Directories beyond this depth are not descended into
+    #[structopt(long)]
+    max_depth: Option<usize>,
+
+    /// Follows symlinks while walking. Symlinked files are cleaned in place at their target, and symlink loops are
+    /// reported rather than followed
+    #[structopt(long)]
+    follow_symlinks: bool,
+
+    /// Processes hidden files and directories, whose names start with a dot
+    #[structopt(long)]
+    hidden: bool,
+
+    /// The number of files to process in parallel, where 0, the default, uses one job per logical CPU. 1 processes
+    /// files sequentially
+    #[structopt(short, long, alias = "threads", allow_hyphen_values = true, parse(try_from_str = parse_jobs))]
+    jobs: Option<usize>,
+
+    /// Prints each file as it's opened and what happened to it, in addition to the report
+    #[structopt(short, long)]
+    verbose: bool,
+
+    /// Doesn't print the report, leaving the exit status as the only outcome of the run
+    #[structopt(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Applies the `.editorconfig` settings that match each file (`trim_trailing_whitespace`, `insert_final_newline`,
+    /// `end_of_line` and `charset`) on top of the other cleaning options
+    #[structopt(long)]
+    editorconfig: bool,
+
+    /// How the report is printed, where github prints a GitHub Actions annotation for each issue found and sarif prints
+    /// a SARIF log of them without modifying any files
+    #[structopt(long, default_value = "text", possible_values = &["text", "json", "github", "sarif"], case_insensitive = true)]
+    format: ReportFormat,
+
+    /// Prints each path in the report relative to the root path it was found under
+    #[structopt(long)]
+    relative_paths: bool,
+
+    /// When to color the report, where auto colors it only when stdout is a terminal
+    #[structopt(long, default_value = "auto", possible_values = &["auto", "always", "never"], case_insensitive = true)]
+    color: ColorChoice,
+
+    /// After the initial run, keeps watching the root paths and cleans selected files again whenever they change,
+    /// until interrupted with Ctrl-C
+    #[structopt(long, conflicts_with_all = &["check", "dry-run", "stdin", "stdin-paths"])]
+    watch: bool,
+
+    /// Prints a completion script for the given shell to stdout and exits
+    #[structopt(long, hidden = true, possible_values = &Shell::variants(), case_insensitive = true)]
+    generate_completions: Option<Shell>,
 }
 
-#[derive(Debug)]
 enum LinemanApplicationError {
     InvalidRootPath(String),
+    InvalidArgument(String),
+    StdinNotCleaned(String),
+    FilesWouldBeCleaned(usize),
+    FilesSkipped(usize),
+    FileFailed(String),
+    WalkDirErrors(usize),
+    InvalidConfig(String),
+    WatchFailed(String),
 }
 
-enum LinemanFileError {
-    FileNotOpened,
-    FileNotCleaned,
+impl LinemanApplicationError {
+    /// The status the process exits with, so that scripts can tell failures apart
+    fn exit_code(&self) -> i32 {
+        match self {
+            LinemanApplicationError::FilesWouldBeCleaned(_) => 1,
+            LinemanApplicationError::InvalidArgument(_) => 2,
+            LinemanApplicationError::InvalidRootPath(_) => 3,
+            LinemanApplicationError::StdinNotCleaned(_) => 4,
+            LinemanApplicationError::FilesSkipped(_) | LinemanApplicationError::FileFailed(_) => 5,
+            LinemanApplicationError::WalkDirErrors(_) => 6,
+            LinemanApplicationError::InvalidConfig(_) => 7,
+            LinemanApplicationError::WatchFailed(_) => 8,
+        }
+    }
 }
 
-fn main() -> Result<(), LinemanApplicationError> {
-    let mut cleaned_file_paths: Vec<PathBuf> = Vec::new();
-    let mut skipped_file_paths: Vec<PathBuf> = Vec::new();
-    let mut walk_dir_errors: Vec<Error> = Vec::new();
-
-    let args = LinemanArgs::from_args();
-    let root_path = args.path;
+impl fmt::Debug for LinemanApplicationError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinemanApplicationError::InvalidRootPath(message)
+            | LinemanApplicationError::InvalidArgument(message)
+            | LinemanApplicationError::StdinNotCleaned(message)
+            | LinemanApplicationError::FileFailed(message)
+            | LinemanApplicationError::InvalidConfig(message)
+            | LinemanApplicationError::WatchFailed(message) => write!(formatter, "{}", message),
+            LinemanApplicationError::FilesWouldBeCleaned(file_count) => {
+                write!(formatter, "{} file(s) would be cleaned", file_count)
+            }
+            LinemanApplicationError::FilesSkipped(file_count) => {
+                write!(
+                    formatter,
+                    "{} file(s) were skipped due to errors",
+                    file_count
+                )
+            }
+            LinemanApplicationError::WalkDirErrors(error_count) => {
+                write!(
+                    formatter,
+                    "{} error(s) occurred while walking directories",
+                    error_count
+                )
+            }
+        }
+    }
+}
 
-    if !root_path.is_dir() {
-        return Err(LinemanApplicationError::InvalidRootPath(
-            "The provided path is not a valid directory".to_string(),
-        ));
+impl LinemanArgs {
+    /// Splits comma-separated extensions and strips a leading dot from each one, so that every way of writing an
+    /// extension is compared the same way against file extensions
+    fn normalize_extensions(&mut self) {
+        if let Some(extensions) = &mut self.extensions {
+            *extensions = extensions
+                .iter()
+                .flat_map(|extension| extension.split(','))
+                .map(|extension| {
+                    let extension = extension.trim();
+                    extension.strip_prefix('.').unwrap_or(extension).to_string()
+                })
+                .filter(|extension| !extension.is_empty())
+                .collect();
+        }
     }
 
-    let normalize_eof_newlines = !args.disable_eof_newline_normalization;
+    /// Fills in settings from a configuration file that weren't passed on the command line
+    fn apply_config(&mut self, config: Config) {
+        if self.extensions.is_none() {
+            self.extensions = config.extensions;
+        }
+
+        if self.exclude.is_empty() {
+            self.exclude = config.exclude.unwrap_or_default();
+        }
 
-    for dir_entry_result in WalkDir::new(root_path) {
-        match dir_entry_result {
-            Ok(dir_entry) => {
-                let path = dir_entry.path();
+        if self.line_ending.is_none() {
+            self.line_ending = config.line_ending;
+        }
 
-                if !path.is_file() {
-                    continue;
-                }
+        // The flag can only disable normalization, so passing it always wins
+        if config.normalize_eof_newlines == Some(false) {
+            self.disable_eof_newline_normalization = true;
+        }
+    }
+}
 
-                if let Some(current_file_extension) = path.extension() {
-                    let should_clean_file = args.extensions.as_ref().map_or(true, |extensions| {
-                        extensions
-                            .iter()
-                            .any(|extension| OsStr::new(extension) == current_file_extension)
-                    });
-
-                    if should_clean_file {
-                        match clean_file(path, normalize_eof_newlines) {
-                            Ok(file_was_cleaned) => {
-                                if file_was_cleaned {
-                                    cleaned_file_paths.push(path.to_path_buf())
-                                }
-                            }
-                            Err(
-                                LinemanFileError::FileNotOpened | LinemanFileError::FileNotCleaned,
-                            ) => skipped_file_paths.push(path.to_path_buf()),
-                        }
-                    }
-                }
+enum LinemanFileError {
+    FileNotOpened(io::Error),
+    FileNotCleaned(io::Error),
+    BackupNotCreated(io::Error),
+    BinaryFile,
+    FileTooLarge(u64),
+}
+
+/// Each error skips the file it happened on, and the reason is kept so the report can tell read failures from write
+/// failures
+impl From<LinemanFileError> for FileOutcome {
+    fn from(file_error: LinemanFileError) -> Self {
+        match file_error {
+            // The file was there when the walk found it, so it was removed while the run was under way
+            LinemanFileError::FileNotOpened(io_error)
+                if io_error.kind() == io::ErrorKind::NotFound =>
+            {
+                FileOutcome::Vanished
+            }
+            LinemanFileError::FileNotOpened(io_error) => FileOutcome::NotRead(io_error),
+            LinemanFileError::FileNotCleaned(io_error)
+                if io_error.kind() == io::ErrorKind::PermissionDenied =>
+            {
+                FileOutcome::ReadOnly(io_error)
             }
-            // TODO: I don't really know what the hell this error is, so I'm just grabbing it and printing it at the end in the report.
-            // When I have a better idea of what it is, I can do something different, I guess
-            Err(walk_dir_error) => walk_dir_errors.push(walk_dir_error),
+            LinemanFileError::FileNotCleaned(io_error) => FileOutcome::NotWritten(io_error),
+            LinemanFileError::BackupNotCreated(io_error) => FileOutcome::NotBackedUp(io_error),
+            LinemanFileError::BinaryFile => FileOutcome::Binary,
+            LinemanFileError::FileTooLarge(file_size) => FileOutcome::TooLarge(file_size),
         }
     }
+}
 
-    print_report(&cleaned_file_paths, &skipped_file_paths, &walk_dir_errors);
+const BACKUP_EXTENSION: &str = "bak";
 
-    Ok(())
+/// The names of directories that are skipped while walking unless `no-default-ignore` is passed, since their contents
+/// are almost never meant to be cleaned
+const DEFAULT_IGNORED_DIR_NAMES: [&str; 4] = [".git", "target", "node_modules", "__pycache__"];
+
+/// Files at least this large are streamed line by line, when the options allow it, instead of being read whole
+const STREAMING_FILE_SIZE: u64 = 1024 * 1024;
+
+/// The size of the read buffer used when streaming, which is also how much of a file is checked for binary content
+const STREAMING_BUFFER_SIZE: usize = 64 * 1024;
+
+#[derive(Debug, Default)]
+struct ReadOptions {
+    force: bool,
+    max_file_size: Option<u64>,
+    lossy: bool,
+}
+
+#[derive(Default)]
+struct WriteOptions {
+    backup: bool,
+    preserve_mtime: bool,
+    chmod_writable: bool,
+    output_dir: Option<PathBuf>,
+    fsync: bool,
 }
 
-fn clean_file(path: &Path, normalize_eof_newlines: bool) -> Result<bool, LinemanFileError> {
-    let file_string = fs::read_to_string(path).map_err(|_| LinemanFileError::FileNotOpened)?;
-    let lines: Vec<&str> = file_string.split_inclusive('\n').collect();
-    let (clean_lines, file_was_cleaned) = clean_lines(&lines, normalize_eof_newlines);
+struct ProcessOptions {
+    clean_options: CleanOptions,
+    read_options: ReadOptions,
+    write_options: WriteOptions,
+    write_changes: bool,
+    verbose: bool,
+    editorconfig: bool,
+    diff_context_lines: Option<usize>,
+    max_line_length: Option<usize>,
+    find_issues: bool,
+    markdown: bool,
+    /// Stops starting new files once one fails
+    fail_fast: bool,
+    /// The files that were clean at the end of a previous run, which are reported as already clean without being read
+    cache: Option<Cache>,
+}
 
-    if file_was_cleaned {
-        let mut file = File::create(path).map_err(|_| LinemanFileError::FileNotCleaned)?;
+/// What happened to a single file, along with a diff of its changes, its overly long lines and the issues found on
+/// its lines when those were asked for
+struct ProcessedFile {
+    file_outcome: FileOutcome,
+    diff: Option<String>,
+    long_lines: Vec<LongLine>,
+    issues: Vec<Issue>,
+    /// Whether the changes replace invalid UTF-8 with U+FFFD
+    is_lossy: bool,
+    /// Whether the changes add the newline that the last line was missing
+    is_missing_final_newline: bool,
+    /// Whether the file ends its lines in more than one style while no line ending is set, leaving the most common
+    /// one to win
+    has_mixed_line_endings: bool,
+}
 
-        for clean_line in clean_lines {
-            // TODO: This needs more thought, as a failure here means the file is probably only partially written to
-            // Better hope your files are version controlled
-            file.write_all(clean_line.as_bytes())
-                .map_err(|_| LinemanFileError::FileNotCleaned)?;
+impl From<FileOutcome> for ProcessedFile {
+    fn from(file_outcome: FileOutcome) -> Self {
+        ProcessedFile {
+            file_outcome,
+            diff: None,
+            long_lines: Vec::new(),
+            issues: Vec::new(),
+            is_lossy: false,
+            is_missing_final_newline: false,
+            has_mixed_line_endings: false,
         }
     }
+}
+
+struct CleanedFile {
+    lines: Vec<String>,
+    original_content: String,
+    encoding: Encoding,
+    clean_stats: CleanStats,
+    is_changed: bool,
+    /// Whether the content was decoded with invalid UTF-8 replaced by U+FFFD, which writing it back makes permanent
+    is_lossy: bool,
+    adds_final_newline: bool,
+    has_mixed_line_endings: bool,
+}
+
+fn main() {
+    let args = match LinemanArgs::from_iter_safe(std::env::args_os()) {
+        Ok(args) => args,
+        // Help and version are printed to stdout and exit successfully, while every other parse error is an invalid
+        // argument, so that it can't be mistaken for files that would be cleaned
+        Err(clap_error)
+            if matches!(
+                clap_error.kind,
+                ErrorKind::HelpDisplayed | ErrorKind::VersionDisplayed
+            ) =>
+        {
+            clap_error.exit()
+        }
+        Err(clap_error) => {
+            eprintln!("{}", clap_error.message);
+            process::exit(LinemanApplicationError::InvalidArgument(clap_error.message).exit_code());
+        }
+    };
 
-    Ok(file_was_cleaned)
+    if let Err(application_error) = run(args) {
+        eprintln!("Error: {:?}", application_error);
+        process::exit(application_error.exit_code());
+    }
 }
 
-fn clean_lines(lines: &[&str], normalize_eof_newlines: bool) -> (Vec<String>, bool) {
-    let mut cleaned_lines: Vec<String> = lines
+/// Runs lineman with already parsed arguments, so that a whole run can be driven without a command line
+fn run(mut args: LinemanArgs) -> Result<(), LinemanApplicationError> {
+    if let Some(shell) = args.generate_completions {
+        LinemanArgs::clap().gen_completions_to("lineman", shell, &mut io::stdout());
+        return Ok(());
+    }
+
+    if let Some(config) =
+        Config::load(args.config.as_deref()).map_err(LinemanApplicationError::InvalidConfig)?
+    {
+        args.apply_config(config);
+    }
+
+    args.normalize_extensions();
+
+    if let Some(invalid_root_path) = args
+        .paths
         .iter()
-        .map(|line| {
-            let line_has_newline = line.ends_with('\n');
-            let trimmed_line = line.trim_end();
-            let cleaned_line = if normalize_eof_newlines || line_has_newline {
-                format!("{}\n", trimmed_line)
-            } else {
-                trimmed_line.to_string()
-            };
-
-            cleaned_line
-        })
-        .rev()
-        .skip_while(|line| normalize_eof_newlines && line.trim_end().is_empty())
-        .collect::<Vec<_>>();
+        .find(|root_path| !root_path.is_dir() && !root_path.is_file())
+    {
+        return Err(LinemanApplicationError::InvalidRootPath(format!(
+            "The provided path is neither a file nor a directory: {}",
+            invalid_root_path.display()
+        )));
+    }
+
+    if args.tab_width == 0 {
+        return Err(LinemanApplicationError::InvalidArgument(
+            "The tab width must be at least 1".to_string(),
+        ));
+    }
+
+    if let Some(output_dir) = args
+        .output_dir
+        .as_ref()
+        .filter(|output_dir| output_dir.is_file())
+    {
+        return Err(LinemanApplicationError::InvalidArgument(format!(
+            "The output directory is a file: {}",
+            output_dir.display()
+        )));
+    }
+
+    if args.max_depth == Some(0) {
+        return Err(LinemanApplicationError::InvalidArgument(
+            "The maximum depth must be at least 1".to_string(),
+        ));
+    }
+
+    if args.stats_only && !matches!(args.format, ReportFormat::Text | ReportFormat::Json) {
+        return Err(LinemanApplicationError::InvalidArgument(
+            "The stats can only be printed as text or JSON".to_string(),
+        ));
+    }
+
+    let jobs = resolve_jobs(args.jobs);
+    let interactive = args.interactive && io::stdin().is_terminal();
+
+    if args.interactive && !interactive {
+        eprintln!("Warning: stdin isn't a terminal, so files are cleaned without asking");
+    }
+
+    let mut process_options = ProcessOptions {
+        clean_options: CleanOptions {
+            normalize_eof_newlines: !args.disable_eof_newline_normalization,
+            normalize_trailing_blanks_only: args.normalize_trailing_blanks_only,
+            tabs_to_spaces: args.tabs_to_spaces,
+            spaces_to_tabs: args.spaces_to_tabs,
+            tab_width: args.tab_width,
+            line_ending: args.line_ending,
+            strip_bom: args.strip_bom,
+            max_consecutive_blank_lines: args.max_consecutive_blank_lines,
+            trailing_blank_lines: args.trailing_blank_lines,
+            trim_leading_blank_lines: args.trim_leading_blank_lines,
+            single_final_newline: args.single_final_newline,
+            strip_stray_cr: args.strip_stray_cr,
+            strip_invisible: args.strip_invisible.then(|| match &args.invisible_chars {
+                Some(invisible_chars) => invisible_chars.chars().collect(),
+                None => INVISIBLE_CHARACTERS.to_vec(),
+            }),
+            trim_trailing_whitespace: !args.disable_trailing_whitespace_trim,
+            trim_chars: args
+                .trim_chars
+                .as_ref()
+                .map(|trim_chars| trim_chars.chars().collect()),
+            // Only Markdown files keep hard line breaks, which is decided for each file
+            markdown_hard_line_breaks: false,
+            preserve_first_line: args.preserve_first_line,
+        },
+        read_options: ReadOptions {
+            force: args.force,
+            max_file_size: args.max_file_size,
+            lossy: args.lossy,
+        },
+        write_options: WriteOptions {
+            backup: args.backup,
+            preserve_mtime: args.preserve_mtime,
+            chmod_writable: args.chmod_writable,
+            output_dir: args.output_dir.clone(),
+            fsync: args.fsync,
+        },
+        // Interactive runs find the files that would change first, then write each one that is confirmed
+        write_changes: !args.check
+            && !args.dry_run
+            && !args.stats_only
+            && !interactive
+            // Annotations and SARIF results describe what a check found, so those formats never write
+            && !matches!(args.format, ReportFormat::Github | ReportFormat::Sarif),
+        verbose: args.verbose,
+        editorconfig: args.editorconfig,
+        diff_context_lines: Some(args.diff_context).filter(|_| args.diff),
+        max_line_length: args.max_line_length,
+        find_issues: matches!(args.format, ReportFormat::Github | ReportFormat::Sarif),
+        markdown: args.markdown,
+        fail_fast: args.fail_fast,
+        cache: None,
+    };
+
+    if args.stdin {
+        return clean_stdin(args.stdin_filename.as_deref(), &process_options);
+    }
+
+    let filter = Filter::new(&args)?;
+    let root_paths = &args.paths;
+    let mut report = Report::default();
+    // Overlapping root paths and symlinks can lead to the same file more than once, which is counted as a duplicate
+    let mut visited_paths = HashSet::new();
+    let stdin_file_paths = if args.stdin_paths {
+        read_stdin_paths(&mut visited_paths, &mut report)
+    } else {
+        Vec::new()
+    };
+
+    if args.list {
+        let mut file_paths = stdin_file_paths;
+
+        for root_path in root_paths {
+            file_paths.extend(select_file_paths(
+                root_path,
+                &filter,
+                &mut visited_paths,
+                &mut report,
+            ));
+        }
+
+        // A closed pipe only means nothing is reading the paths anymore
+        let _ = write_paths(&mut io::stdout().lock(), &file_paths, args.print0);
+
+        // Errors from the walk still decide the exit status, even though the report isn't printed
+        return match report_error(&report, false) {
+            Some(application_error) => Err(application_error),
+            None => Ok(()),
+        };
+    }
+
+    process_options.cache = args.cache.as_deref().map(|cache_path| {
+        // The cleaning options are kept for each file, while these apply to every file alike
+        let settings = format!(
+            "{:?} {:?} {:?}",
+            process_options.read_options, process_options.max_line_length, args.format
+        );
+
+        Cache::load(cache_path, settings)
+    });
+
+    if args.verbose {
+        eprintln!("Processing files with {} job(s)", jobs);
+    }
+
+    // Each root path adds its files to the progress bar once it has been walked
+    let progress_bar = if args.quiet || !io::stdout().is_terminal() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(0).with_style(
+            ProgressStyle::with_template("{bar:40} {pos}/{len} files ({eta} left)")
+                .expect("the progress bar template is valid"),
+        )
+    };
+    let thread_pool = ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build()
+        .map_err(|error| LinemanApplicationError::InvalidArgument(error.to_string()))?;
+    let process_result = thread_pool.install(|| {
+        if args.stdin_paths {
+            report.merge(process_paths(
+                stdin_file_paths,
+                &process_options,
+                &progress_bar,
+            )?);
+        }
+
+        for root_path in root_paths {
+            report.merge(process_tree(
+                root_path,
+                &process_options,
+                &filter,
+                &mut visited_paths,
+                &progress_bar,
+            )?);
+        }
+
+        Ok::<(), LinemanApplicationError>(())
+    });
+
+    progress_bar.finish_and_clear();
+    process_result?;
+
+    if interactive {
+        process_options.write_changes = true;
+        confirm_changes(&mut report, &process_options);
+    }
+
+    if let (Some(mut cache), Some(cache_path)) = (process_options.cache.take(), &args.cache) {
+        cache.update(&report, |path| {
+            file_clean_options(path, &process_options)
+                .ok()
+                .map(Cow::into_owned)
+        });
+
+        if let Err(io_error) = cache.save(cache_path) {
+            eprintln!(
+                "Warning: the cache could not be written: {}: {}",
+                cache_path.display(),
+                io_error
+            );
+        }
+    }
+
+    if !args.quiet {
+        if args.relative_paths {
+            report.relativize_paths(&args.paths);
+        }
+
+        if args.print0 {
+            let changed_file_paths: Vec<PathBuf> = report
+                .cleaned_files
+                .iter()
+                .chain(&report.would_be_cleaned_files)
+                .map(|(path, _)| path.clone())
+                .collect();
+
+            // A closed pipe only means nothing is reading the paths anymore
+            let _ = write_paths(&mut io::stdout().lock(), &changed_file_paths, true);
+        } else if args.stats_only {
+            report.print_stats(args.format);
+        } else {
+            report.print(args.format, args.color.use_color());
+        }
+    }
+
+    if args.summary_json {
+        println!("{}", report.summary_json());
+    }
+
+    if args.watch {
+        return watch::watch(&args, &process_options, &filter);
+    }
+
+    match report_error(&report, args.check) {
+        Some(application_error) => Err(application_error),
+        None => Ok(()),
+    }
+}
+
+/// Walks a root path and processes every file selected in it, returning what happened to each. A file given as the
+/// root path is processed on its own. Files already in the visited paths are counted as duplicates instead
+fn process_tree(
+    root_path: &Path,
+    process_options: &ProcessOptions,
+    filter: &Filter,
+    visited_paths: &mut HashSet<PathBuf>,
+    progress_bar: &ProgressBar,
+) -> Result<Report, LinemanApplicationError> {
+    let mut report = Report::default();
+    let file_paths = select_file_paths(root_path, filter, visited_paths, &mut report);
+
+    report.merge(process_paths(file_paths, process_options, progress_bar)?);
 
-    cleaned_lines.reverse();
+    Ok(report)
+}
+
+/// Processes the given files, returning what happened to each
+fn process_paths(
+    file_paths: Vec<PathBuf>,
+    process_options: &ProcessOptions,
+    progress_bar: &ProgressBar,
+) -> Result<Report, LinemanApplicationError> {
+    let mut report = Report::default();
+
+    if process_options.verbose {
+        progress_bar.suspend(|| eprintln!("Processing {} file(s)", file_paths.len()));
+    }
 
-    // This is probably slow, maybe a better method can be implemented later
-    let lines_were_cleaned = lines != cleaned_lines;
+    progress_bar.inc_length(file_paths.len() as u64);
 
-    (cleaned_lines, lines_were_cleaned)
+    let processed_files = process_files(&file_paths, process_options, progress_bar)?;
+
+    // Outcomes are collected in the same order as the paths, so the report doesn't depend on thread scheduling
+    for (path, processed_file) in file_paths.into_iter().zip(processed_files) {
+        record_processed_file(&mut report, path, processed_file);
+    }
+
+    Ok(report)
 }
 
-fn print_report(
-    cleaned_file_paths: &[PathBuf],
-    skipped_file_paths: &[PathBuf],
-    walk_dir_errors: &[Error],
-) {
-    let indent = " ".repeat(4);
+/// Reads newline-separated paths from stdin, recording the ones that don't exist as missing
+fn read_stdin_paths(visited_paths: &mut HashSet<PathBuf>, report: &mut Report) -> Vec<PathBuf> {
+    let mut file_paths = Vec::new();
+
+    for line in io::stdin().lock().lines().map_while(Result::ok) {
+        let line = line.trim_end_matches('\r');
 
-    if !cleaned_file_paths.is_empty() {
-        println!("Cleaned Files:");
+        if line.is_empty() {
+            continue;
+        }
+
+        let path = PathBuf::from(line);
 
-        for cleaned_file_path in cleaned_file_paths {
-            println!("{}{}", indent, cleaned_file_path.display());
+        if !path.exists() {
+            report.missing_file_paths.push(path);
+        } else if visit_file(&path, visited_paths) {
+            file_paths.push(path);
+        } else {
+            report.duplicate_file_count += 1;
         }
     }
 
-    if !skipped_file_paths.is_empty() {
-        println!("Skipped Files:");
+    file_paths
+}
+
+/// Records a file as visited under its canonical path, returning whether this is the first time it was reached. A
+/// file that can't be canonicalized always counts as new, so that whatever is wrong with it is reported when it's
+/// processed
+fn visit_file(path: &Path, visited_paths: &mut HashSet<PathBuf>) -> bool {
+    match fs::canonicalize(path) {
+        Ok(canonical_path) => visited_paths.insert(canonical_path),
+        Err(_) => true,
+    }
+}
 
-        for skipped_file_path in skipped_file_paths {
-            println!("{}{}", indent, skipped_file_path.display());
+/// Walks a root path, returning the files the filter selects that haven't been visited yet, and recording the errors
+/// and duplicates met along the way. A file given as the root path is returned as-is, without applying the file
+/// selection
+fn select_file_paths(
+    root_path: &Path,
+    filter: &Filter,
+    visited_paths: &mut HashSet<PathBuf>,
+    report: &mut Report,
+) -> Vec<PathBuf> {
+    if root_path.is_file() {
+        if !visit_file(root_path, visited_paths) {
+            report.duplicate_file_count += 1;
+            return Vec::new();
         }
+
+        return vec![root_path.to_path_buf()];
     }
 
-    if !walk_dir_errors.is_empty() {
-        println!("Walkdir Errors:");
+    let mut file_paths = Vec::new();
+    let mut ignore_files = IgnoreFiles::new(root_path, ignore_file_names(filter.no_ignore));
+    let dir_entry_results = WalkDir::new(root_path)
+        .max_depth(filter.max_depth.unwrap_or(usize::MAX))
+        .follow_links(filter.follow_symlinks)
+        .into_iter()
+        .filter_entry(|dir_entry| {
+            if dir_entry.depth() == 0 {
+                return true;
+            }
+
+            let is_dir = dir_entry.file_type().is_dir();
+
+            if is_dir && filter.is_ignored_dir(dir_entry.file_name()) {
+                return false;
+            }
+
+            // Hidden directories are pruned here, while hidden files are left to the file selection
+            if is_dir && !filter.include_hidden && is_hidden(Path::new(dir_entry.file_name())) {
+                return false;
+            }
+
+            !ignore_files.is_ignored(dir_entry.path(), is_dir)
+        });
+
+    for dir_entry_result in dir_entry_results {
+        match dir_entry_result {
+            Ok(dir_entry) => {
+                let path = dir_entry.path();
+
+                if !path.is_file() {
+                    continue;
+                }
+
+                // Backups made during this run could otherwise be picked up by the walk and cleaned themselves
+                if filter.skip_backups && path.extension() == Some(OsStr::new(BACKUP_EXTENSION)) {
+                    continue;
+                }
+
+                // The same goes for the cache, which is rewritten at the end of the run
+                if filter.is_cache_file(path) {
+                    continue;
+                }
+
+                let relative_path = path.strip_prefix(root_path).unwrap_or(path);
+
+                if !filter.matches(path, relative_path) {
+                    continue;
+                }
 
-        for walk_dir_error in walk_dir_errors {
-            println!("{}{}", indent, walk_dir_error);
+                if visit_file(path, visited_paths) {
+                    file_paths.push(path.to_path_buf());
+                } else {
+                    report.duplicate_file_count += 1;
+                }
+            }
+            // Unreadable directories and symlink loops are classified when the report is printed
+            Err(walk_dir_error) => report.walk_dir_errors.push(walk_dir_error),
         }
     }
+
+    file_paths
 }
 
-#[test]
-fn clean_lines_with_trailing_spaces() {
-    let input_lines = [
-        "def main():   \n",
-        "    print(\"Hello World\")    \n",
-        "    \n",
-        "if __name__ == \"__main__\":    \n",
-        "    main()    \n",
-    ];
+/// Processes every file in parallel, returning what happened to each in the same order as the paths. Files the cache
+/// holds as unchanged since they were clean are passed over. With `fail_fast`, files that haven't been started yet are
+/// left alone once one fails, and the first failure in path order is returned
+fn process_files(
+    file_paths: &[PathBuf],
+    process_options: &ProcessOptions,
+    progress_bar: &ProgressBar,
+) -> Result<Vec<ProcessedFile>, LinemanApplicationError> {
+    let fail_fast = process_options.fail_fast;
+    let failed = AtomicBool::new(false);
+    let processed_files: Vec<Option<ProcessedFile>> = file_paths
+        .par_iter()
+        .map(|path| {
+            if failed.load(Ordering::SeqCst) {
+                return None;
+            }
+
+            if process_options.cache.as_ref().is_some_and(|cache| {
+                file_clean_options(path, process_options)
+                    .is_ok_and(|clean_options| cache.is_fresh(path, &clean_options))
+            }) {
+                progress_bar.inc(1);
+                return Some(FileOutcome::AlreadyClean.into());
+            }
+
+            let processed_file = process_file(path, process_options, progress_bar);
+            progress_bar.inc(1);
 
-    let expected_output_lines = [
-        "def main():\n",
-        "    print(\"Hello World\")\n",
-        "\n",
-        "if __name__ == \"__main__\":\n",
-        "    main()\n",
-    ];
+            if fail_fast && processed_file.file_outcome.is_error() {
+                failed.store(true, Ordering::SeqCst);
+            }
+
+            Some(processed_file)
+        })
+        .collect();
 
-    let (output_lines, lines_have_changes) = clean_lines(&input_lines, true);
+    if let Some((path, processed_file)) =
+        file_paths
+            .iter()
+            .zip(&processed_files)
+            .find(|(_, processed_file)| {
+                fail_fast
+                    && processed_file
+                        .as_ref()
+                        .is_some_and(|processed_file| processed_file.file_outcome.is_error())
+            })
+    {
+        return Err(LinemanApplicationError::FileFailed(format!(
+            "{}: {}",
+            path.display(),
+            processed_file
+                .as_ref()
+                .expect("only processed files are found")
+                .file_outcome
+        )));
+    }
 
-    assert_eq!(expected_output_lines.to_vec(), output_lines);
-    assert_eq!(lines_have_changes, true);
+    // Files are only passed over after a failure, so without one every file was processed
+    Ok(processed_files.into_iter().flatten().collect())
 }
 
-#[test]
-fn clean_lines_with_trailing_tabs() {
-    let input_lines = [
-        "def main():\t\n",
-        "    print(\"Hello World\")\t\n",
-        "\t\n",
-        "if __name__ == \"__main__\":\t\n",
-        "    main()\t\n",
-    ];
+/// Writes each path on its own line, or terminated by a NUL byte so that paths containing newlines survive. NUL
+/// terminated paths are written as their raw bytes rather than displayed, so no path is altered on the way
+fn write_paths<W: Write>(writer: &mut W, paths: &[PathBuf], print0: bool) -> io::Result<()> {
+    for path in paths {
+        if print0 {
+            writer.write_all(path.as_os_str().as_encoded_bytes())?;
+            writer.write_all(b"\0")?;
+        } else {
+            writeln!(writer, "{}", path.display())?;
+        }
+    }
+
+    writer.flush()
+}
+
+/// An answer to the prompt asked before writing a file in an interactive run
+#[derive(Debug, PartialEq, Eq)]
+enum Confirmation {
+    Yes,
+    No,
+    All,
+    Quit,
+}
 
-    let expected_output_lines = [
-        "def main():\n",
-        "    print(\"Hello World\")\n",
-        "\n",
-        "if __name__ == \"__main__\":\n",
-        "    main()\n",
-    ];
+/// Asks about each file in the report that would be cleaned, in path order, and cleans the ones that are confirmed.
+/// Files that are declined, or left over after quitting, stay as files that would be cleaned
+fn confirm_changes(report: &mut Report, process_options: &ProcessOptions) {
+    let mut input = io::stdin().lock();
+    let mut clean_all = false;
+    let mut quit = false;
 
-    let (output_lines, lines_have_changes) = clean_lines(&input_lines, true);
+    for (path, clean_stats) in mem::take(&mut report.would_be_cleaned_files) {
+        let is_confirmed = !quit
+            && (clean_all
+                || match prompt_confirmation(&mut input, &path, &clean_stats) {
+                    Confirmation::Yes => true,
+                    Confirmation::No => false,
+                    Confirmation::All => {
+                        clean_all = true;
+                        true
+                    }
+                    Confirmation::Quit => {
+                        quit = true;
+                        false
+                    }
+                });
 
-    assert_eq!(expected_output_lines.to_vec(), output_lines);
-    assert_eq!(lines_have_changes, true);
+        if is_confirmed {
+            let processed_file = process_file(&path, process_options, &ProgressBar::hidden());
+            report.record(path, processed_file.file_outcome);
+        } else {
+            report.would_be_cleaned_files.push((path, clean_stats));
+        }
+    }
 }
 
-#[test]
-fn add_newline_to_end_of_file() {
-    let input_lines = [
-        "def main():\n",
-        "    print(\"Hello World\")\n",
-        "\n",
-        "if __name__ == \"__main__\":\n",
-        "    main()",
-    ];
+/// Asks whether to clean a file until a valid answer is read. Running out of input counts as quitting
+fn prompt_confirmation<R: BufRead>(
+    input: &mut R,
+    path: &Path,
+    clean_stats: &CleanStats,
+) -> Confirmation {
+    loop {
+        eprint!("Clean {} ({})? [y,n,a,q] ", path.display(), clean_stats);
 
-    let expected_output_lines = [
-        "def main():\n",
-        "    print(\"Hello World\")\n",
-        "\n",
-        "if __name__ == \"__main__\":\n",
-        "    main()\n",
-    ];
+        let mut answer = String::new();
 
-    let (output_lines, lines_have_changes) = clean_lines(&input_lines, true);
+        match input.read_line(&mut answer) {
+            Ok(0) | Err(_) => return Confirmation::Quit,
+            Ok(_) => {}
+        }
 
-    assert_eq!(expected_output_lines.to_vec(), output_lines);
-    assert_eq!(lines_have_changes, true);
+        match answer.trim().to_lowercase().as_str() {
+            "y" | "yes" => return Confirmation::Yes,
+            "n" | "no" => return Confirmation::No,
+            "a" | "all" => return Confirmation::All,
+            "q" | "quit" => return Confirmation::Quit,
+            _ => eprintln!("Please answer y (yes), n (no), a (all) or q (quit)"),
+        }
+    }
 }
 
-#[test]
-fn do_not_add_newline_to_end_of_file() {
-    let input_lines = [
-        "def main():\n",
-        "    print(\"Hello World\")\n",
-        "\n",
-        "if __name__ == \"__main__\":\n",
-        "    main()",
-    ];
+/// Adds a processed file to the report, printing its diff if it has one and warning about it if cleaning it replaces
+/// invalid UTF-8. Files that are missing a final newline or that mix line endings are listed as well as recorded with
+/// their outcome
+fn record_processed_file(report: &mut Report, path: PathBuf, processed_file: ProcessedFile) {
+    if let Some(diff) = processed_file.diff {
+        print!("{}", diff);
+    }
 
-    let expected_output_lines = [
-        "def main():\n",
-        "    print(\"Hello World\")\n",
-        "\n",
-        "if __name__ == \"__main__\":\n",
-        "    main()",
-    ];
+    if processed_file.is_lossy && !processed_file.file_outcome.is_error() {
+        eprintln!(
+            "Warning: {} isn't valid UTF-8, so cleaning it replaces its invalid bytes with U+FFFD",
+            path.display()
+        );
+    }
 
-    let (output_lines, lines_have_changes) = clean_lines(&input_lines, false);
-
-    assert_eq!(expected_output_lines.to_vec(), output_lines);
-    assert_eq!(lines_have_changes, false);
-}
-
-#[test]
-fn remove_excessive_newlines_from_end_of_file() {
-    let input_lines = [
-        "def main():\n",
-        "    print(\"Hello World\")\n",
-        "\n",
-        "if __name__ == \"__main__\":\n",
-        "    main()\n",
-        "\n",
-        "\n",
-        "\n",
-    ];
-
-    let expected_output_lines = [
-        "def main():\n",
-        "    print(\"Hello World\")\n",
-        "\n",
-        "if __name__ == \"__main__\":\n",
-        "    main()\n",
-    ];
-
-    let (output_lines, lines_have_changes) = clean_lines(&input_lines, true);
-
-    assert_eq!(expected_output_lines.to_vec(), output_lines);
-    assert_eq!(lines_have_changes, true);
-}
-
-#[test]
-fn do_not_remove_excessive_newlines_from_end_of_file() {
-    let input_lines = [
-        "def main():\n",
-        "    print(\"Hello World\")\n",
-        "\n",
-        "if __name__ == \"__main__\":\n",
-        "    main()\n",
-        "\n",
-        "\n",
-        "\n",
-    ];
-
-    let expected_output_lines = [
-        "def main():\n",
-        "    print(\"Hello World\")\n",
-        "\n",
-        "if __name__ == \"__main__\":\n",
-        "    main()\n",
-        "\n",
-        "\n",
-        "\n",
-    ];
-
-    let (output_lines, lines_have_changes) = clean_lines(&input_lines, false);
-
-    assert_eq!(expected_output_lines.to_vec(), output_lines);
-    assert_eq!(lines_have_changes, false);
+    if !processed_file.long_lines.is_empty() {
+        report
+            .long_line_files
+            .push((path.clone(), processed_file.long_lines));
+    }
+
+    if !processed_file.issues.is_empty() {
+        report
+            .issue_files
+            .push((path.clone(), processed_file.issues));
+    }
+
+    if processed_file.is_missing_final_newline && !processed_file.file_outcome.is_error() {
+        report.missing_final_newline_file_paths.push(path.clone());
+    }
+
+    if processed_file.has_mixed_line_endings && !processed_file.file_outcome.is_error() {
+        report.mixed_line_ending_file_paths.push(path.clone());
+    }
+
+    report.record(path, processed_file.file_outcome);
+}
+
+/// The gitignore-style files that exclude paths from the walk. `.linemanignore` files come last so that, within a
+/// directory, their rules win over those of `.gitignore`
+fn ignore_file_names(no_ignore: bool) -> Vec<&'static str> {
+    if no_ignore {
+        vec![LINEMANIGNORE_FILE_NAME]
+    } else {
+        vec![".gitignore", LINEMANIGNORE_FILE_NAME]
+    }
+}
+
+/// Turns a finished report into the error behind its exit code
+fn report_error(report: &Report, check: bool) -> Option<LinemanApplicationError> {
+    match report.exit_code(check) {
+        0 => None,
+        1 => Some(LinemanApplicationError::FilesWouldBeCleaned(
+            report.would_be_cleaned_files.len(),
+        )),
+        5 => Some(LinemanApplicationError::FilesSkipped(
+            report.failed_file_count(),
+        )),
+        6 => Some(LinemanApplicationError::WalkDirErrors(
+            report.walk_dir_errors.len(),
+        )),
+        exit_code => unreachable!("a report never exits with {}", exit_code),
+    }
+}
+
+/// Cleans everything read from stdin and writes the result to stdout, with the rules of the file it's named as if
+/// there is one
+fn clean_stdin(
+    stdin_filename: Option<&Path>,
+    process_options: &ProcessOptions,
+) -> Result<(), LinemanApplicationError> {
+    let clean_options = match stdin_filename {
+        Some(stdin_filename) => file_clean_options(stdin_filename, process_options)
+            .map_err(|error| LinemanApplicationError::StdinNotCleaned(error.to_string()))?,
+        None => Cow::Borrowed(&process_options.clean_options),
+    };
+    let mut input = String::new();
+
+    io::stdin()
+        .read_to_string(&mut input)
+        .map_err(|error| LinemanApplicationError::StdinNotCleaned(error.to_string()))?;
+
+    io::stdout()
+        .write_all(clean_string(&input, &clean_options).as_bytes())
+        .map_err(|error| LinemanApplicationError::StdinNotCleaned(error.to_string()))
+}
+
+/// Cleans a single file, returning what happened to it. Verbose output is printed around the progress bar so the two
+/// don't garble each other
+fn process_file(
+    path: &Path,
+    process_options: &ProcessOptions,
+    progress_bar: &ProgressBar,
+) -> ProcessedFile {
+    if process_options.verbose {
+        progress_bar.suspend(|| eprintln!("Opening {}", path.display()));
+    }
+
+    let processed_file = clean_and_write_file(path, process_options);
+
+    if process_options.verbose {
+        progress_bar.suspend(|| eprintln!("{}: {}", path.display(), processed_file.file_outcome));
+    }
+
+    processed_file
+}
+
+/// The cleaning options for a file, which its `.editorconfig` settings and its extension can change from the ones
+/// used for every other file
+fn file_clean_options<'a>(
+    path: &Path,
+    process_options: &'a ProcessOptions,
+) -> io::Result<Cow<'a, CleanOptions>> {
+    let mut clean_options = Cow::Borrowed(&process_options.clean_options);
+
+    if process_options.editorconfig {
+        clean_options = Cow::Owned(editorconfig_clean_options(path, &clean_options)?);
+    }
+
+    if process_options.markdown && is_markdown_file(path) {
+        clean_options.to_mut().markdown_hard_line_breaks = true;
+    }
+
+    Ok(clean_options)
+}
+
+fn clean_and_write_file(path: &Path, process_options: &ProcessOptions) -> ProcessedFile {
+    let clean_options = match file_clean_options(path, process_options) {
+        Ok(clean_options) => clean_options,
+        Err(io_error) => return FileOutcome::NotRead(io_error).into(),
+    };
+    let clean_options = clean_options.as_ref();
+
+    // Diffs, long line reports and issues need the whole file, so only plain cleaning is streamed
+    if process_options.diff_context_lines.is_none()
+        && process_options.max_line_length.is_none()
+        && !process_options.find_issues
+        && is_streamable(clean_options)
+    {
+        match stream_file(path, clean_options, process_options) {
+            Ok(Some(processed_file)) => return processed_file,
+            Ok(None) => {}
+            Err(file_error) => return FileOutcome::from(file_error).into(),
+        }
+    }
+
+    let cleaned_file = match clean_file(path, clean_options, &process_options.read_options) {
+        Ok(cleaned_file) => cleaned_file,
+        Err(file_error) => return FileOutcome::from(file_error).into(),
+    };
+    let long_lines = process_options
+        .max_line_length
+        .map(|max_line_length| find_long_lines(&cleaned_file.lines, max_line_length))
+        .unwrap_or_default();
+
+    if !cleaned_file.is_changed {
+        // The output directory gets a copy of every selected file, including the ones that were already clean
+        let copy_result = match &process_options.write_options.output_dir {
+            Some(_) if process_options.write_changes => {
+                write_destination(path, &process_options.write_options).and_then(|destination| {
+                    fs::copy(path, destination)
+                        .map(|_| ())
+                        .map_err(LinemanFileError::FileNotCleaned)
+                })
+            }
+            _ => Ok(()),
+        };
+
+        return ProcessedFile {
+            file_outcome: match copy_result {
+                Ok(()) => FileOutcome::AlreadyClean,
+                Err(file_error) => FileOutcome::from(file_error),
+            },
+            diff: None,
+            long_lines,
+            issues: Vec::new(),
+            is_lossy: false,
+            is_missing_final_newline: false,
+            has_mixed_line_endings: cleaned_file.has_mixed_line_endings,
+        };
+    }
+
+    let issues = if process_options.find_issues {
+        find_issues(&split_lines(&cleaned_file.original_content), clean_options)
+    } else {
+        Vec::new()
+    };
+
+    if !process_options.write_changes {
+        return ProcessedFile {
+            file_outcome: FileOutcome::WouldBeCleaned(cleaned_file.clean_stats),
+            diff: process_options
+                .diff_context_lines
+                .map(|context_lines| diff_file(path, &cleaned_file, context_lines)),
+            long_lines,
+            issues,
+            is_lossy: cleaned_file.is_lossy,
+            is_missing_final_newline: cleaned_file.adds_final_newline,
+            has_mixed_line_endings: cleaned_file.has_mixed_line_endings,
+        };
+    }
+
+    let file_outcome = match write_file(path, &cleaned_file, &process_options.write_options) {
+        Ok(()) => FileOutcome::Cleaned(cleaned_file.clean_stats),
+        Err(file_error) => FileOutcome::from(file_error),
+    };
+
+    ProcessedFile {
+        file_outcome,
+        diff: None,
+        long_lines,
+        issues,
+        is_lossy: cleaned_file.is_lossy,
+        is_missing_final_newline: cleaned_file.adds_final_newline,
+        has_mixed_line_endings: cleaned_file.has_mixed_line_endings,
+    }
+}
+
+/// Renders the changes cleaning makes to a file as a unified diff
+fn diff_file(path: &Path, cleaned_file: &CleanedFile, context_lines: usize) -> String {
+    let cleaned_content = cleaned_file.lines.concat();
+    let original_header = format!("a/{}", path.display());
+    let cleaned_header = format!("b/{}", path.display());
+
+    TextDiff::from_lines(&cleaned_file.original_content, &cleaned_content)
+        .unified_diff()
+        .context_radius(context_lines)
+        .header(&original_header, &cleaned_header)
+        .to_string()
+}
+
+/// Which files are selected for cleaning, and how root paths are walked to find them
+struct Filter {
+    extensions: Option<Vec<String>>,
+    include_patterns: PathPatterns,
+    exclude_patterns: PathPatterns,
+    file_names: Vec<String>,
+    ignore_extension_case: bool,
+    include_no_extension: bool,
+    include_hidden: bool,
+    no_ignore: bool,
+    max_depth: Option<usize>,
+    follow_symlinks: bool,
+    /// Whether backup files are passed over, since ones made during the run would otherwise be cleaned themselves
+    skip_backups: bool,
+    /// The names of directories that are never walked
+    ignored_dir_names: Vec<String>,
+    /// Where the cache given with `--cache` already is, so that it isn't cleaned like any other file
+    cache_path: Option<PathBuf>,
+}
+
+impl Filter {
+    fn new(args: &LinemanArgs) -> Result<Self, LinemanApplicationError> {
+        Ok(Filter {
+            extensions: args.extensions.clone(),
+            include_patterns: PathPatterns::new(&args.include, &args.include_regex)?,
+            exclude_patterns: PathPatterns::new(&args.exclude, &args.exclude_regex)?,
+            file_names: args.filenames.clone(),
+            ignore_extension_case: args.ignore_extension_case,
+            include_no_extension: args.include_no_extension,
+            include_hidden: args.hidden,
+            no_ignore: args.no_ignore,
+            max_depth: args.max_depth,
+            follow_symlinks: args.follow_symlinks,
+            skip_backups: args.backup,
+            ignored_dir_names: DEFAULT_IGNORED_DIR_NAMES
+                .iter()
+                .filter(|_| !args.no_default_ignore)
+                .map(|dir_name| dir_name.to_string())
+                .chain(args.ignore_dir.iter().cloned())
+                .collect(),
+            cache_path: args
+                .cache
+                .as_deref()
+                .and_then(|cache_path| fs::canonicalize(cache_path).ok()),
+        })
+    }
+
+    fn is_cache_file(&self, path: &Path) -> bool {
+        self.cache_path.as_ref().is_some_and(|cache_path| {
+            path.file_name() == cache_path.file_name()
+                && fs::canonicalize(path).ok().as_ref() == Some(cache_path)
+        })
+    }
+
+    fn is_ignored_dir(&self, dir_name: &OsStr) -> bool {
+        self.ignored_dir_names
+            .iter()
+            .any(|ignored_dir_name| OsStr::new(ignored_dir_name) == dir_name)
+    }
+
+    /// Decides whether a file is selected for cleaning, which happens when its extension is in the extension list,
+    /// its path matches an include pattern or its name is in the file name list, and its path matches no exclude
+    /// pattern. Without any of these lists, every file with an extension is selected. Hidden files, and files in hidden
+    /// directories, are never selected unless hidden files are included, though a hidden file named in the file name
+    /// list still is. Globs are matched against the path relative to the root path it was found under
+    fn matches(&self, path: &Path, relative_path: &Path) -> bool {
+        if self.exclude_patterns.is_match(path, relative_path) {
+            return false;
+        }
+
+        let file_name_is_selected = relative_path.file_name().is_some_and(|current_file_name| {
+            self.file_names
+                .iter()
+                .any(|file_name| OsStr::new(file_name) == current_file_name)
+        });
+
+        if !self.include_hidden {
+            let file_is_hidden =
+                is_hidden(Path::new(relative_path.file_name().unwrap_or_default()));
+            let directory_is_hidden = relative_path.parent().is_some_and(is_hidden);
+
+            if directory_is_hidden || (file_is_hidden && !file_name_is_selected) {
+                return false;
+            }
+        }
+
+        self.extension_is_selected(relative_path)
+            || file_name_is_selected
+            || self.include_patterns.is_match(path, relative_path)
+    }
+
+    fn extension_is_selected(&self, path: &Path) -> bool {
+        let Some(current_file_extension) = path.extension() else {
+            return self.include_no_extension;
+        };
+
+        match &self.extensions {
+            Some(extensions) => extensions.iter().any(|extension| {
+                if self.ignore_extension_case {
+                    extension.to_lowercase()
+                        == current_file_extension.to_string_lossy().to_lowercase()
+                } else {
+                    OsStr::new(extension) == current_file_extension
+                }
+            }),
+            None => self.include_patterns.is_empty() && self.file_names.is_empty(),
+        }
+    }
+}
+
+/// The patterns that include or exclude files: globs matched against paths relative to the root path, and regular
+/// expressions matched against full paths
+struct PathPatterns {
+    globs: GlobSet,
+    regexes: RegexSet,
+}
+
+impl PathPatterns {
+    /// Compiles the globs and regular expressions once, so that invalid ones are reported before the walk starts
+    fn new(globs: &[String], regexes: &[String]) -> Result<Self, LinemanApplicationError> {
+        let regexes = RegexSet::new(regexes).map_err(|error| {
+            LinemanApplicationError::InvalidArgument(format!(
+                "Invalid regular expression: {}",
+                error
+            ))
+        })?;
+
+        Ok(PathPatterns {
+            globs: build_glob_set(globs)?,
+            regexes,
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.globs.is_empty() && self.regexes.is_empty()
+    }
+
+    fn is_match(&self, path: &Path, relative_path: &Path) -> bool {
+        self.globs.is_match(relative_path) || self.regexes.is_match(&path.to_string_lossy())
+    }
+}
+
+fn build_glob_set(patterns: &[String]) -> Result<GlobSet, LinemanApplicationError> {
+    let mut glob_set_builder = GlobSetBuilder::new();
+
+    for pattern in patterns {
+        let glob = GlobBuilder::new(pattern)
+            .literal_separator(true)
+            .build()
+            .map_err(|error| LinemanApplicationError::InvalidArgument(error.to_string()))?;
+        glob_set_builder.add(glob);
+    }
+
+    glob_set_builder
+        .build()
+        .map_err(|error| LinemanApplicationError::InvalidArgument(error.to_string()))
+}
+
+fn is_markdown_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| {
+            extension.eq_ignore_ascii_case("md") || extension.eq_ignore_ascii_case("markdown")
+        })
+}
+
+/// Checks whether any component of a path is hidden, meaning that its name starts with a dot
+fn is_hidden(path: &Path) -> bool {
+    path.components().any(|component| match component {
+        Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    })
+}
+
+/// Computes the cleaned content of a file without writing it, along with whether cleaning changed it. Files
+/// that look binary are rejected unless `force` is set, and files larger than the maximum file size are never read
+fn clean_file(
+    path: &Path,
+    clean_options: &CleanOptions,
+    read_options: &ReadOptions,
+) -> Result<CleanedFile, LinemanFileError> {
+    if let Some(max_file_size) = read_options.max_file_size {
+        let file_size = fs::metadata(path)
+            .map_err(LinemanFileError::FileNotOpened)?
+            .len();
+
+        if file_size > max_file_size {
+            return Err(LinemanFileError::FileTooLarge(file_size));
+        }
+    }
+
+    let file_bytes = fs::read(path).map_err(LinemanFileError::FileNotOpened)?;
+    let encoding = Encoding::detect(&file_bytes);
+
+    // UTF-16 text is full of NUL bytes, so only UTF-8 content is checked
+    if encoding == Encoding::Utf8 && !read_options.force && is_binary(&file_bytes) {
+        return Err(LinemanFileError::BinaryFile);
+    }
+    let (file_string, is_lossy) = match encoding.decode(&file_bytes) {
+        Some(file_string) => (file_string, false),
+        None if encoding == Encoding::Utf8 && read_options.lossy => {
+            (String::from_utf8_lossy(&file_bytes).into_owned(), true)
+        }
+        None => {
+            return Err(LinemanFileError::FileNotOpened(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "the file isn't valid text in its detected encoding",
+            )))
+        }
+    };
+    let content = if clean_options.strip_bom {
+        strip_bom(&file_string)
+    } else {
+        &file_string
+    };
+    let (clean_lines, _) = clean_lines(&split_lines(content), clean_options);
+    let adds_final_newline = adds_final_newline(&split_lines(&file_string), &clean_lines);
+    // A line ending to convert to settles the mix on purpose, so only the guess at the most common one is warned about
+    let has_mixed_line_endings =
+        clean_options.line_ending.is_none() && has_mixed_line_endings(file_string.as_bytes());
+    let clean_stats = CleanStats {
+        changed_line_count: count_changed_lines(&split_lines(&file_string), &clean_lines),
+        original_byte_count: file_bytes.len(),
+        cleaned_byte_count: encoding.bom().len()
+            + clean_lines
+                .iter()
+                .map(|clean_line| encoding.encode(clean_line).len())
+                .sum::<usize>(),
+    };
+    // Comparing the content itself, rather than trusting the cleaning rules to report their changes, guarantees that
+    // clean files are never rewritten
+    let is_changed = clean_lines.concat() != file_string;
+
+    Ok(CleanedFile {
+        lines: clean_lines,
+        original_content: file_string,
+        encoding,
+        clean_stats,
+        is_changed,
+        is_lossy,
+        adds_final_newline,
+        has_mixed_line_endings,
+    })
+}
+
+/// Cleans a large file by streaming it line by line, either into a temporary file that replaces the original or,
+/// when changes aren't written, into nothing. Returns `None` for files that are small enough to read whole, or whose
+/// content can't be streamed, such as UTF-16 or CR-terminated text. Lossy decoding needs the whole file, since its
+/// invalid bytes are only found once strict decoding fails, so nothing is streamed with it
+fn stream_file(
+    path: &Path,
+    clean_options: &CleanOptions,
+    process_options: &ProcessOptions,
+) -> Result<Option<ProcessedFile>, LinemanFileError> {
+    let read_options = &process_options.read_options;
+    let file_size = fs::metadata(path)
+        .map_err(LinemanFileError::FileNotOpened)?
+        .len();
+
+    if file_size < STREAMING_FILE_SIZE || read_options.lossy {
+        return Ok(None);
+    }
+
+    if let Some(max_file_size) = read_options.max_file_size {
+        if file_size > max_file_size {
+            return Err(LinemanFileError::FileTooLarge(file_size));
+        }
+    }
+
+    let mut reader = BufReader::with_capacity(
+        STREAMING_BUFFER_SIZE,
+        File::open(path).map_err(LinemanFileError::FileNotOpened)?,
+    );
+    let file_start = reader.fill_buf().map_err(LinemanFileError::FileNotOpened)?;
+
+    if Encoding::detect(file_start) != Encoding::Utf8 {
+        return Ok(None);
+    }
+
+    if !read_options.force && is_binary(file_start) {
+        return Err(LinemanFileError::BinaryFile);
+    }
+
+    let line_ending = match clean_options.line_ending {
+        Some(line_ending) => line_ending,
+        None => {
+            let file = File::open(path).map_err(LinemanFileError::FileNotOpened)?;
+
+            match detect_stream_line_ending(BufReader::with_capacity(STREAMING_BUFFER_SIZE, file))
+                .map_err(LinemanFileError::FileNotOpened)?
+            {
+                Some(LineEnding::Cr) => return Ok(None),
+                Some(line_ending) => line_ending,
+                None => LineEnding::Lf,
+            }
+        }
+    };
+
+    if !process_options.write_changes {
+        let stream_stats = clean_stream(reader, &mut io::sink(), clean_options, line_ending)
+            .map_err(LinemanFileError::FileNotOpened)?;
+
+        return Ok(Some(streamed_file(
+            FileOutcome::WouldBeCleaned,
+            stream_stats,
+            clean_options,
+        )));
+    }
+
+    let destination = &write_destination(path, &process_options.write_options)?;
+    let temp_path = temp_file_path(destination);
+    let stream_result = File::create(&temp_path).and_then(|temp_file| {
+        let mut writer = BufWriter::new(temp_file);
+        let stream_stats = clean_stream(reader, &mut writer, clean_options, line_ending)?;
+        writer.flush()?;
+
+        if process_options.write_options.fsync {
+            writer.get_ref().sync_all()?;
+        }
+
+        Ok(stream_stats)
+    });
+    let is_output_copy = process_options.write_options.output_dir.is_some();
+    let stream_stats = match stream_result {
+        Ok(stream_stats) if stream_stats.changed_line_count > 0 || is_output_copy => stream_stats,
+        Ok(stream_stats) => {
+            let _ = fs::remove_file(&temp_path);
+            return Ok(Some(streamed_file(
+                FileOutcome::Cleaned,
+                stream_stats,
+                clean_options,
+            )));
+        }
+        Err(io_error) => {
+            let _ = fs::remove_file(&temp_path);
+            return Err(LinemanFileError::FileNotCleaned(io_error));
+        }
+    };
+
+    // Whether anything changed is only known once the whole file has been streamed, so the backup is made last
+    if process_options.write_options.backup {
+        if let Err(io_error) = fs::copy(destination, backup_file_path(destination)) {
+            let _ = fs::remove_file(&temp_path);
+            return Err(LinemanFileError::BackupNotCreated(io_error));
+        }
+    }
+
+    let write_result = copy_file_metadata(
+        path,
+        &temp_path,
+        process_options.write_options.preserve_mtime,
+    )
+    .and_then(|()| fs::rename(&temp_path, destination));
+
+    if let Err(io_error) = write_result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(LinemanFileError::FileNotCleaned(io_error));
+    }
+
+    Ok(Some(streamed_file(
+        FileOutcome::Cleaned,
+        stream_stats,
+        clean_options,
+    )))
+}
+
+/// A file that streaming processed, which is already clean if nothing changed and otherwise gets the outcome built
+/// from what the stream counted
+fn streamed_file(
+    file_outcome: fn(CleanStats) -> FileOutcome,
+    stream_stats: StreamStats,
+    clean_options: &CleanOptions,
+) -> ProcessedFile {
+    let file_outcome = if stream_stats.changed_line_count == 0 {
+        FileOutcome::AlreadyClean
+    } else {
+        file_outcome(CleanStats {
+            changed_line_count: stream_stats.changed_line_count,
+            original_byte_count: stream_stats.input_byte_count,
+            cleaned_byte_count: stream_stats.output_byte_count,
+        })
+    };
+
+    ProcessedFile {
+        is_missing_final_newline: stream_stats.added_final_newline,
+        has_mixed_line_endings: clean_options.line_ending.is_none()
+            && stream_stats.has_mixed_line_endings,
+        ..file_outcome.into()
+    }
+}
+
+/// Writes the cleaned lines to a temporary file next to the original and renames it over the original, so an
+/// interrupted write never leaves a partially written file behind. Symlinks are resolved first, so that their
+/// targets are cleaned in place rather than the links being replaced
+fn write_file(
+    path: &Path,
+    cleaned_file: &CleanedFile,
+    write_options: &WriteOptions,
+) -> Result<(), LinemanFileError> {
+    let destination = &write_destination(path, write_options)?;
+
+    if write_options.backup {
+        fs::copy(destination, backup_file_path(destination))
+            .map_err(LinemanFileError::BackupNotCreated)?;
+    }
+
+    let temp_path = temp_file_path(destination);
+
+    let write_result = write_temp_file(path, &temp_path, cleaned_file, write_options)
+        .and_then(|()| fs::rename(&temp_path, destination));
+
+    if let Err(io_error) = write_result {
+        // The original file is untouched at this point, so only the temporary file needs cleaning up
+        let _ = fs::remove_file(&temp_path);
+        return Err(LinemanFileError::FileNotCleaned(io_error));
+    }
+
+    Ok(())
+}
+
+/// The path cleaned content is written to. This is the file itself, with symlinks resolved, unless there is an output
+/// directory, in which case it is the file's mirrored path under it
+fn write_destination(
+    path: &Path,
+    write_options: &WriteOptions,
+) -> Result<PathBuf, LinemanFileError> {
+    let Some(output_dir) = &write_options.output_dir else {
+        let path = fs::canonicalize(path).map_err(LinemanFileError::FileNotCleaned)?;
+        ensure_writable(&path, write_options)?;
+
+        return Ok(path);
+    };
+
+    let destination = output_dir.join(mirrored_path(path));
+
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).map_err(LinemanFileError::FileNotCleaned)?;
+    }
+
+    Ok(destination)
+}
+
+/// The path a file is mirrored at under the output directory, which is the path it was found at without any root,
+/// `.` or `..` components, so that `-p src` mirrors `src/main.rs` at `<output-dir>/src/main.rs`
+fn mirrored_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect()
+}
+
+/// Refuses to clean a read-only file unless `chmod_writable` is set. Renaming a temporary file over the file would
+/// otherwise replace it whatever its own permissions are. The temporary file takes on the original's permissions, so
+/// a file cleaned with `chmod_writable` stays read-only
+fn ensure_writable(path: &Path, write_options: &WriteOptions) -> Result<(), LinemanFileError> {
+    let metadata = fs::metadata(path).map_err(LinemanFileError::FileNotCleaned)?;
+
+    if metadata.permissions().readonly() && !write_options.chmod_writable {
+        return Err(LinemanFileError::FileNotCleaned(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "permission denied (read-only)",
+        )));
+    }
+
+    Ok(())
+}
+
+fn write_temp_file(
+    path: &Path,
+    temp_path: &Path,
+    cleaned_file: &CleanedFile,
+    write_options: &WriteOptions,
+) -> io::Result<()> {
+    let mut temp_file = BufWriter::new(File::create(temp_path)?);
+
+    temp_file.write_all(cleaned_file.encoding.bom())?;
+
+    for clean_line in &cleaned_file.lines {
+        temp_file.write_all(&cleaned_file.encoding.encode(clean_line))?;
+    }
+
+    // Dropping a `BufWriter` silently discards any error from its final write, so it is flushed explicitly
+    temp_file.flush()?;
+
+    if write_options.fsync {
+        temp_file.get_ref().sync_all()?;
+    }
+
+    copy_file_metadata(path, temp_path, write_options.preserve_mtime)
+}
+
+fn copy_file_metadata(path: &Path, temp_path: &Path, preserve_mtime: bool) -> io::Result<()> {
+    let metadata = fs::metadata(path)?;
+
+    // The temporary file replaces the original, so it has to carry over the original's permissions (such as the
+    // executable bit on scripts)
+    fs::set_permissions(temp_path, metadata.permissions())?;
+
+    // Renaming keeps the temporary file's modification time, so setting it here carries over to the original path
+    if preserve_mtime {
+        filetime::set_file_mtime(temp_path, FileTime::from_last_modification_time(&metadata))?;
+    }
+
+    Ok(())
+}
+
+/// Parses the number of jobs. Negative numbers get their own message, since the parsing error alone doesn't say why
+/// they are invalid
+fn parse_jobs(jobs: &str) -> Result<usize, String> {
+    let jobs = jobs.trim();
+
+    if jobs.parse::<i64>().is_ok_and(|jobs| jobs < 0) {
+        return Err(format!(
+            "The number of jobs can't be negative: {}. Use 0 for one job per logical CPU",
+            jobs
+        ));
+    }
+
+    jobs.parse()
+        .map_err(|_| format!("Invalid number of jobs: {}", jobs))
+}
+
+/// The number of worker threads to process files with, where no number of jobs or 0 uses one per logical CPU
+fn resolve_jobs(jobs: Option<usize>) -> usize {
+    match jobs {
+        Some(0) | None => {
+            thread::available_parallelism().map_or(1, |parallelism| parallelism.get())
+        }
+        Some(jobs) => jobs,
+    }
+}
+
+/// Parses a human-readable size such as `512`, `64KB` or `10MB` into bytes. Units are case-insensitive powers of 1024
+fn parse_file_size(file_size: &str) -> Result<u64, String> {
+    let file_size = file_size.trim();
+    let unit_start = file_size
+        .find(|character: char| !character.is_ascii_digit())
+        .unwrap_or(file_size.len());
+    let (number, unit) = file_size.split_at(unit_start);
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" | "kib" => 1024,
+        "m" | "mb" | "mib" => 1024 * 1024,
+        "g" | "gb" | "gib" => 1024 * 1024 * 1024,
+        _ => return Err(format!("Unknown file size unit: {}", unit)),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("Invalid file size: {}", file_size))?;
+
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("File size is too large: {}", file_size))
+}
+
+fn backup_file_path(path: &Path) -> PathBuf {
+    let mut backup_path = path.as_os_str().to_os_string();
+    backup_path.push(".");
+    backup_path.push(BACKUP_EXTENSION);
+
+    PathBuf::from(backup_path)
+}
+
+fn temp_file_path(path: &Path) -> PathBuf {
+    let file_name = path
+        .file_name()
+        .map(|file_name| file_name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+
+    path.with_file_name(format!(".{}.lineman-{}.tmp", file_name, process::id()))
+}
+
+/// Checks whether a path is a temporary file written by this process while cleaning
+fn is_temp_file(path: &Path) -> bool {
+    let suffix = format!(".lineman-{}.tmp", process::id());
+
+    path.file_name()
+        .is_some_and(|file_name| file_name.to_string_lossy().ends_with(&suffix))
+}
+
+/// Builds the filter a run with these arguments would select files with
+#[cfg(test)]
+fn test_filter(args: &[&str]) -> Filter {
+    let mut args = LinemanArgs::from_iter(["lineman", "-p", "."].iter().chain(args));
+    args.normalize_extensions();
+
+    Filter::new(&args).unwrap()
+}
+
+/// The options files are processed with in tests, writing the changes with every other option left off
+#[cfg(test)]
+fn test_process_options() -> ProcessOptions {
+    ProcessOptions {
+        clean_options: CleanOptions::default(),
+        read_options: ReadOptions::default(),
+        write_options: WriteOptions::default(),
+        write_changes: true,
+        verbose: false,
+        editorconfig: false,
+        diff_context_lines: None,
+        max_line_length: None,
+        find_issues: false,
+        markdown: false,
+        fail_fast: false,
+        cache: None,
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn write_file_preserves_permissions() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("script.sh");
+    fs::write(&path, "echo \"Hello World\"   \n").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let cleaned_file = clean_file(&path, &CleanOptions::default(), &ReadOptions::default())
+        .ok()
+        .unwrap();
+    let write_options = WriteOptions::default();
+    assert!(write_file(&path, &cleaned_file, &write_options).is_ok());
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "echo \"Hello World\"\n");
+    assert_eq!(
+        fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+        0o755
+    );
+}
+
+#[test]
+fn write_file_skips_read_only_files_unless_chmod_writable() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("main.py");
+    fs::write(&path, "def main():   \n").unwrap();
+
+    let mut permissions = fs::metadata(&path).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&path, permissions).unwrap();
+
+    let cleaned_file = clean_file(&path, &CleanOptions::default(), &ReadOptions::default())
+        .ok()
+        .unwrap();
+    let mut write_options = WriteOptions::default();
+    let file_error = write_file(&path, &cleaned_file, &write_options)
+        .err()
+        .unwrap();
+
+    assert!(matches!(
+        FileOutcome::from(file_error),
+        FileOutcome::ReadOnly(io_error) if io_error.to_string() == "permission denied (read-only)"
+    ));
+    assert_eq!(fs::read_to_string(&path).unwrap(), "def main():   \n");
+
+    write_options.chmod_writable = true;
+    assert!(write_file(&path, &cleaned_file, &write_options).is_ok());
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "def main():\n");
+    assert!(fs::metadata(&path).unwrap().permissions().readonly());
+}
+
+#[test]
+fn write_file_to_output_dir_leaves_the_original_alone() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let source_dir = temp_dir.path().join("src");
+    let output_dir = temp_dir.path().join("cleaned");
+    let path = source_dir.join("nested/main.py");
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(&path, "def main():   \n").unwrap();
+
+    let cleaned_file = clean_file(&path, &CleanOptions::default(), &ReadOptions::default())
+        .ok()
+        .unwrap();
+    let write_options = WriteOptions {
+        output_dir: Some(output_dir.clone()),
+        ..WriteOptions::default()
+    };
+    assert!(write_file(&path, &cleaned_file, &write_options).is_ok());
+
+    let mirrored_path = output_dir.join(mirrored_path(&path));
+    assert!(mirrored_path.ends_with("src/nested/main.py"));
+    assert_eq!(fs::read_to_string(&mirrored_path).unwrap(), "def main():\n");
+    assert_eq!(fs::read_to_string(&path).unwrap(), "def main():   \n");
+}
+
+#[test]
+fn mirror_paths_without_root_or_parent_components() {
+    assert_eq!(
+        mirrored_path(Path::new("./src/main.rs")),
+        PathBuf::from("src/main.rs")
+    );
+    assert_eq!(
+        mirrored_path(Path::new("../other/lib.rs")),
+        PathBuf::from("other/lib.rs")
+    );
+    assert_eq!(
+        mirrored_path(Path::new("/repository/README.md")),
+        PathBuf::from("repository/README.md")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn write_file_cleans_symlink_targets_in_place() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let target_path = temp_dir.path().join("main.py");
+    let link_path = temp_dir.path().join("link.py");
+    fs::write(&target_path, "def main():   \n").unwrap();
+    std::os::unix::fs::symlink(&target_path, &link_path).unwrap();
+
+    let cleaned_file = clean_file(
+        &link_path,
+        &CleanOptions::default(),
+        &ReadOptions::default(),
+    )
+    .ok()
+    .unwrap();
+    let write_options = WriteOptions::default();
+    assert!(write_file(&link_path, &cleaned_file, &write_options).is_ok());
+
+    assert!(fs::symlink_metadata(&link_path)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(fs::read_to_string(&target_path).unwrap(), "def main():\n");
+}
+
+#[test]
+fn write_file_with_fsync_replaces_the_original() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("main.rs");
+    fs::write(&path, "fn main() {}   \n\n").unwrap();
+
+    let cleaned_file = clean_file(&path, &CleanOptions::default(), &ReadOptions::default())
+        .ok()
+        .unwrap();
+    let write_options = WriteOptions {
+        fsync: true,
+        ..WriteOptions::default()
+    };
+    assert!(write_file(&path, &cleaned_file, &write_options).is_ok());
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "fn main() {}\n");
+    assert!(!temp_file_path(&path).exists());
+}
+
+#[test]
+fn write_file_preserves_mtime() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("main.py");
+    fs::write(&path, "def main():   \n").unwrap();
+
+    let original_mtime = FileTime::from_unix_time(1_000_000_000, 0);
+    filetime::set_file_mtime(&path, original_mtime).unwrap();
+
+    let cleaned_file = clean_file(&path, &CleanOptions::default(), &ReadOptions::default())
+        .ok()
+        .unwrap();
+    let write_options = WriteOptions {
+        preserve_mtime: true,
+        ..WriteOptions::default()
+    };
+    assert!(write_file(&path, &cleaned_file, &write_options).is_ok());
+
+    let metadata = fs::metadata(&path).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "def main():\n");
+    assert_eq!(
+        FileTime::from_last_modification_time(&metadata),
+        original_mtime
+    );
+}
+
+#[test]
+fn clean_utf_16_le_file_round_trip() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("main.py");
+    let encode_utf_16_le = |text: &str| -> Vec<u8> {
+        let code_units = text.encode_utf16().flat_map(u16::to_le_bytes);
+        [0xFF, 0xFE].into_iter().chain(code_units).collect()
+    };
+    fs::write(
+        &path,
+        encode_utf_16_le("def main():   \n    print(\"héllo\")\t\n\n"),
+    )
+    .unwrap();
+
+    let cleaned_file = clean_file(&path, &CleanOptions::default(), &ReadOptions::default())
+        .ok()
+        .unwrap();
+    let write_options = WriteOptions::default();
+    assert_eq!(cleaned_file.encoding, Encoding::Utf16Le);
+    assert!(write_file(&path, &cleaned_file, &write_options).is_ok());
+
+    assert_eq!(
+        fs::read(&path).unwrap(),
+        encode_utf_16_le("def main():\n    print(\"héllo\")\n")
+    );
+}
+
+#[test]
+fn clean_file_skips_binary_files_unless_forced() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("image.dat");
+    fs::write(&path, b"GIF89a  \n\x00\x01\x02\n").unwrap();
+
+    assert!(matches!(
+        clean_file(&path, &CleanOptions::default(), &ReadOptions::default()),
+        Err(LinemanFileError::BinaryFile)
+    ));
+    assert!(matches!(
+        clean_file(
+            &path,
+            &CleanOptions::default(),
+            &ReadOptions {
+                force: true,
+                ..ReadOptions::default()
+            }
+        ),
+        Ok(cleaned_file) if cleaned_file.is_changed
+    ));
+}
+
+#[test]
+fn clean_file_leaves_empty_files_empty() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let empty_path = temp_dir.path().join("empty.txt");
+    let newline_path = temp_dir.path().join("newline.txt");
+    fs::write(&empty_path, "").unwrap();
+    fs::write(&newline_path, "\n").unwrap();
+
+    let cleaned_file = clean_file(
+        &empty_path,
+        &CleanOptions::default(),
+        &ReadOptions::default(),
+    )
+    .ok()
+    .unwrap();
+    assert!(!cleaned_file.is_changed);
+    assert!(!cleaned_file.adds_final_newline);
+    assert!(cleaned_file.lines.is_empty());
+
+    let cleaned_file = clean_file(
+        &newline_path,
+        &CleanOptions::default(),
+        &ReadOptions::default(),
+    )
+    .ok()
+    .unwrap();
+    assert!(cleaned_file.is_changed);
+    assert!(cleaned_file.lines.is_empty());
+    assert_eq!(cleaned_file.clean_stats.bytes_saved(), 1);
+}
+
+#[test]
+fn clean_file_replaces_invalid_utf_8_only_when_lossy() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("latin1.txt");
+    fs::write(&path, b"caf\xe9  \n").unwrap();
+
+    let lossy_read_options = ReadOptions {
+        lossy: true,
+        ..ReadOptions::default()
+    };
+
+    assert!(matches!(
+        clean_file(&path, &CleanOptions::default(), &ReadOptions::default()),
+        Err(LinemanFileError::FileNotOpened(io_error)) if io_error.kind() == io::ErrorKind::InvalidData
+    ));
+
+    let cleaned_file = clean_file(&path, &CleanOptions::default(), &lossy_read_options)
+        .ok()
+        .unwrap();
+
+    assert!(cleaned_file.is_changed);
+    assert!(cleaned_file.is_lossy);
+    assert_eq!(cleaned_file.lines, vec!["caf\u{fffd}\n".to_string()]);
+
+    // A file with nothing else to clean is left alone rather than rewritten with replacement characters
+    fs::write(&path, b"caf\xe9\n").unwrap();
+
+    let cleaned_file = clean_file(&path, &CleanOptions::default(), &lossy_read_options)
+        .ok()
+        .unwrap();
+
+    assert!(!cleaned_file.is_changed);
+}
+
+#[test]
+fn skip_default_and_extra_ignored_directories() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+
+    for file_name in [
+        "src/main.rs",
+        "target/debug/build.rs",
+        "node_modules/package/index.rs",
+        "vendor/lib.rs",
+        "src/target/generated.rs",
+    ] {
+        let path = root_path.join(file_name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "content\n").unwrap();
+    }
+
+    let selected_file_names = |args: &[&str]| {
+        let mut file_paths = select_file_paths(
+            root_path,
+            &test_filter(args),
+            &mut HashSet::new(),
+            &mut Report::default(),
+        );
+        file_paths.sort();
+
+        file_paths
+            .iter()
+            .map(|path| path.strip_prefix(root_path).unwrap().to_path_buf())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(
+        selected_file_names(&["-e", "rs"]),
+        vec![PathBuf::from("src/main.rs"), PathBuf::from("vendor/lib.rs")]
+    );
+    assert_eq!(
+        selected_file_names(&["-e", "rs", "--ignore-dir", "vendor"]),
+        vec![PathBuf::from("src/main.rs")]
+    );
+    assert_eq!(
+        selected_file_names(&["-e", "rs", "--no-default-ignore"]).len(),
+        5
+    );
+}
+
+#[test]
+fn select_files_by_extension_or_include_glob() {
+    let filter = test_filter(&["-e", "py", "-i", "src/**/*.rs", "Makefile"]);
+
+    assert!(filter.matches(Path::new("main.py"), Path::new("main.py")));
+    assert!(filter.matches(Path::new("src/main.rs"), Path::new("src/main.rs")));
+    assert!(filter.matches(Path::new("src/bin/cli.rs"), Path::new("src/bin/cli.rs")));
+    assert!(filter.matches(Path::new("Makefile"), Path::new("Makefile")));
+    assert!(!filter.matches(Path::new("build.rs"), Path::new("build.rs")));
+    assert!(!filter.matches(Path::new("docs/Makefile"), Path::new("docs/Makefile")));
+
+    let filter = test_filter(&["-i", "src/**/*.rs", "Makefile"]);
+
+    assert!(filter.matches(Path::new("src/main.rs"), Path::new("src/main.rs")));
+    assert!(!filter.matches(Path::new("main.py"), Path::new("main.py")));
+}
+
+#[test]
+fn select_every_file_with_an_extension_without_filters() {
+    let filter = test_filter(&[]);
+
+    assert!(filter.matches(Path::new("src/main.rs"), Path::new("src/main.rs")));
+    assert!(filter.matches(Path::new("README.md"), Path::new("README.md")));
+    assert!(!filter.matches(Path::new("Makefile"), Path::new("Makefile")));
+}
+
+#[test]
+fn exclude_globs_take_precedence_over_include_globs() {
+    let path = Path::new("src/generated/schema.rs");
+
+    let filter = test_filter(&[
+        "-e",
+        "rs",
+        "-i",
+        "src/**/*.rs",
+        "--exclude",
+        "**/generated/**",
+    ]);
+    assert!(filter.matches(Path::new("src/main.rs"), Path::new("src/main.rs")));
+    assert!(!filter.matches(path, path));
+
+    let filter = test_filter(&["-i", "src/**/*.rs", "--exclude", "**/generated/**"]);
+    assert!(!filter.matches(path, path));
+}
+
+#[test]
+fn match_extensions_case_insensitively() {
+    let path = Path::new("README.MD");
+
+    assert!(test_filter(&["-e", "md", "--ignore-extension-case"]).matches(path, path));
+    assert!(!test_filter(&["-e", "md"]).matches(path, path));
+}
+
+#[test]
+fn select_files_by_exact_file_name() {
+    let filter = test_filter(&["--filenames", "Makefile", ".gitignore"]);
+    let is_selected = |path: &str| filter.matches(Path::new(path), Path::new(path));
+
+    assert!(is_selected("Makefile"));
+    assert!(is_selected("docs/Makefile"));
+    assert!(is_selected(".gitignore"));
+    assert!(!is_selected("Makefile.bak"));
+    assert!(!is_selected("src/main.rs"));
+}
+
+#[test]
+fn select_files_without_an_extension_only_when_included() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let script_path = temp_dir.path().join("run");
+    let binary_path = temp_dir.path().join("tool");
+    fs::write(&script_path, "#!/bin/sh   \necho run\n").unwrap();
+    fs::write(&binary_path, b"\x7fELF\x00\x01   \n").unwrap();
+
+    let filter = test_filter(&["-e", "rs"]);
+    assert!(!filter.matches(&script_path, Path::new("run")));
+
+    let filter = test_filter(&["-e", "rs", "--include-no-extension"]);
+    assert!(filter.matches(&script_path, Path::new("run")));
+    assert!(filter.matches(&binary_path, Path::new("tool")));
+    assert!(!filter.matches(Path::new("notes.txt"), Path::new("notes.txt")));
+    assert!(!filter.matches(Path::new(".profile"), Path::new(".profile")));
+
+    let args = LinemanArgs::from_iter([
+        OsStr::new("lineman"),
+        OsStr::new("-p"),
+        temp_dir.path().as_os_str(),
+        OsStr::new("-e"),
+        OsStr::new("rs"),
+        OsStr::new("--include-no-extension"),
+        OsStr::new("--quiet"),
+    ]);
+    assert!(run(args).is_ok());
+
+    assert_eq!(
+        fs::read_to_string(&script_path).unwrap(),
+        "#!/bin/sh\necho run\n"
+    );
+    assert_eq!(fs::read(&binary_path).unwrap(), b"\x7fELF\x00\x01   \n");
+}
+
+#[test]
+fn count_files_reached_through_nested_root_paths_as_duplicates() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path().to_path_buf();
+    let nested_root_path = root_path.join("src");
+    fs::create_dir_all(&nested_root_path).unwrap();
+    fs::write(root_path.join("build.rs"), "fn main() {}\n").unwrap();
+    fs::write(nested_root_path.join("main.rs"), "fn main() {}\n").unwrap();
+
+    let filter = test_filter(&["-e", "rs"]);
+    let mut visited_paths = HashSet::new();
+    let mut report = Report::default();
+
+    assert_eq!(
+        select_file_paths(&root_path, &filter, &mut visited_paths, &mut report).len(),
+        2
+    );
+    assert!(
+        select_file_paths(&nested_root_path, &filter, &mut visited_paths, &mut report).is_empty()
+    );
+    assert!(select_file_paths(&root_path, &filter, &mut visited_paths, &mut report).is_empty());
+    assert_eq!(report.duplicate_file_count, 3);
+}
+
+#[test]
+fn skip_hidden_files_and_directories_unless_included() {
+    let filter = test_filter(&["-e", "local", "rs", "yml", "--filenames", ".gitignore"]);
+    let hidden_filter = test_filter(&[
+        "-e",
+        "local",
+        "rs",
+        "yml",
+        "--filenames",
+        ".gitignore",
+        "--hidden",
+    ]);
+    let is_selected = |path: &str, include_hidden: bool| {
+        let filter = if include_hidden {
+            &hidden_filter
+        } else {
+            &filter
+        };
+
+        filter.matches(Path::new(path), Path::new(path))
+    };
+
+    assert!(!is_selected(".env.local", false));
+    assert!(!is_selected(".github/workflows/ci.yml", false));
+    assert!(!is_selected("src/.cache/output.rs", false));
+    assert!(is_selected(".gitignore", false));
+    assert!(is_selected("src/main.rs", false));
+
+    assert!(is_selected(".env.local", true));
+    assert!(is_selected(".github/workflows/ci.yml", true));
+}
+
+#[test]
+fn report_errors_map_to_distinct_exit_codes() {
+    let mut report = Report::default();
+    assert!(report_error(&report, true).is_none());
+
+    report.record(PathBuf::from("binary.png"), FileOutcome::Binary);
+    report.record(
+        PathBuf::from("dirty.txt"),
+        FileOutcome::WouldBeCleaned(CleanStats {
+            changed_line_count: 1,
+            original_byte_count: 2,
+            cleaned_byte_count: 1,
+        }),
+    );
+    assert!(report_error(&report, false).is_none());
+    assert_eq!(report_error(&report, true).unwrap().exit_code(), 1);
+
+    report.record(
+        PathBuf::from("unreadable.txt"),
+        FileOutcome::NotRead(io::Error::from(io::ErrorKind::PermissionDenied)),
+    );
+    assert_eq!(report_error(&report, true).unwrap().exit_code(), 5);
+
+    let exit_codes: Vec<i32> = [
+        LinemanApplicationError::FilesWouldBeCleaned(1),
+        LinemanApplicationError::InvalidArgument(String::new()),
+        LinemanApplicationError::InvalidRootPath(String::new()),
+        LinemanApplicationError::StdinNotCleaned(String::new()),
+        LinemanApplicationError::FilesSkipped(1),
+        LinemanApplicationError::WalkDirErrors(1),
+        LinemanApplicationError::InvalidConfig(String::new()),
+        LinemanApplicationError::WatchFailed(String::new()),
+    ]
+    .iter()
+    .map(LinemanApplicationError::exit_code)
+    .collect();
+    assert_eq!(exit_codes, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+}
+
+#[test]
+fn skip_unreadable_and_unwritable_files_separately() {
+    let mut report = Report::default();
+
+    for (path, file_error) in [
+        (
+            "unreadable.txt",
+            LinemanFileError::FileNotOpened(io::Error::from(io::ErrorKind::PermissionDenied)),
+        ),
+        (
+            "unwritable.txt",
+            LinemanFileError::FileNotCleaned(io::Error::from(io::ErrorKind::StorageFull)),
+        ),
+        (
+            "read_only.txt",
+            LinemanFileError::FileNotCleaned(io::Error::from(io::ErrorKind::PermissionDenied)),
+        ),
+        ("binary.png", LinemanFileError::BinaryFile),
+        ("large.log", LinemanFileError::FileTooLarge(1024)),
+    ] {
+        report.record(PathBuf::from(path), FileOutcome::from(file_error));
+    }
+
+    assert_eq!(
+        report.unreadable_files[0].0,
+        PathBuf::from("unreadable.txt")
+    );
+    assert_eq!(
+        report.unwritable_files[0].0,
+        PathBuf::from("unwritable.txt")
+    );
+    assert_eq!(report.read_only_files[0].0, PathBuf::from("read_only.txt"));
+    assert_eq!(report.binary_file_paths, vec![PathBuf::from("binary.png")]);
+    assert_eq!(
+        report.oversized_files,
+        vec![(PathBuf::from("large.log"), 1024)]
+    );
+    assert_eq!(report_error(&report, false).unwrap().exit_code(), 5);
+}
+
+#[test]
+fn fail_fast_stops_at_the_first_failed_file() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let cleaned_path = temp_dir.path().join("a.txt");
+    // A directory can't be read as a file, while a missing file would only count as removed during the run
+    let unreadable_path = temp_dir.path().join("b.txt");
+    fs::write(&cleaned_path, "dirty \n").unwrap();
+    fs::create_dir(&unreadable_path).unwrap();
+
+    let mut process_options = test_process_options();
+    let file_paths = vec![cleaned_path.clone(), unreadable_path.clone()];
+    let progress_bar = ProgressBar::hidden();
+
+    let processed_files = process_files(&file_paths, &process_options, &progress_bar).unwrap();
+    assert!(matches!(
+        processed_files[1].file_outcome,
+        FileOutcome::NotRead(_)
+    ));
+
+    fs::write(&cleaned_path, "dirty \n").unwrap();
+    process_options.fail_fast = true;
+
+    let Err(application_error) = process_files(&file_paths, &process_options, &progress_bar) else {
+        panic!("the unreadable file should stop the run");
+    };
+    assert_eq!(application_error.exit_code(), 5);
+    assert!(format!("{:?}", application_error).starts_with(&unreadable_path.display().to_string()));
+
+    // Files cleaned before the failure are not rolled back
+    assert_eq!(fs::read_to_string(&cleaned_path).unwrap(), "dirty\n");
+}
+
+#[test]
+fn process_tree_reports_every_selected_file() {
+    use structopt::StructOpt;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    fs::create_dir_all(root_path.join("src")).unwrap();
+    fs::write(root_path.join("src/main.rs"), "fn main() {}   \n").unwrap();
+    fs::write(root_path.join("src/lib.rs"), "pub fn lib() {}\n").unwrap();
+    fs::write(root_path.join("src/logo.rs"), b"\x00binary").unwrap();
+    fs::write(root_path.join("notes.txt"), "notes   \n").unwrap();
+
+    let args = LinemanArgs::from_iter([
+        OsStr::new("lineman"),
+        OsStr::new("-p"),
+        root_path.as_os_str(),
+        OsStr::new("-e"),
+        OsStr::new("rs"),
+    ]);
+    let process_options = test_process_options();
+    let report = process_tree(
+        root_path,
+        &process_options,
+        &Filter::new(&args).unwrap(),
+        &mut HashSet::new(),
+        &ProgressBar::hidden(),
+    )
+    .unwrap();
+
+    let cleaned_file_paths: Vec<&PathBuf> =
+        report.cleaned_files.iter().map(|(path, _)| path).collect();
+    assert_eq!(cleaned_file_paths, vec![&root_path.join("src/main.rs")]);
+    assert_eq!(
+        report.already_clean_file_paths,
+        vec![root_path.join("src/lib.rs")]
+    );
+    assert_eq!(
+        report.binary_file_paths,
+        vec![root_path.join("src/logo.rs")]
+    );
+    assert_eq!(
+        fs::read_to_string(root_path.join("src/main.rs")).unwrap(),
+        "fn main() {}\n"
+    );
+    assert_eq!(
+        fs::read_to_string(root_path.join("notes.txt")).unwrap(),
+        "notes   \n"
+    );
+}
+
+#[test]
+fn detect_markdown_files_by_extension() {
+    assert!(is_markdown_file(Path::new("README.md")));
+    assert!(is_markdown_file(Path::new("docs/guide.Markdown")));
+    assert!(!is_markdown_file(Path::new("src/main.rs")));
+    assert!(!is_markdown_file(Path::new("md")));
+}
+
+#[test]
+fn write_paths_terminated_by_newlines_or_nul_bytes() {
+    let paths = [PathBuf::from("src/main.rs"), PathBuf::from("my\nnotes.txt")];
+    let mut output = Vec::new();
+
+    write_paths(&mut output, &paths, false).unwrap();
+    assert_eq!(output, b"src/main.rs\nmy\nnotes.txt\n");
+
+    output.clear();
+    write_paths(&mut output, &paths, true).unwrap();
+    assert_eq!(output, b"src/main.rs\0my\nnotes.txt\0");
+}
+
+#[test]
+fn clean_file_keeps_the_underlying_io_error() {
+    let temp_dir = tempfile::tempdir().unwrap();
+
+    assert!(matches!(
+        clean_file(&temp_dir.path().join("missing.txt"), &CleanOptions::default(), &ReadOptions::default()),
+        Err(LinemanFileError::FileNotOpened(io_error)) if io_error.kind() == io::ErrorKind::NotFound
+    ));
+}
+
+#[test]
+fn parse_human_readable_file_sizes() {
+    assert_eq!(parse_file_size("0"), Ok(0));
+    assert_eq!(parse_file_size("512"), Ok(512));
+    assert_eq!(parse_file_size("512B"), Ok(512));
+    assert_eq!(parse_file_size("64KB"), Ok(64 * 1024));
+    assert_eq!(parse_file_size("10mb"), Ok(10 * 1024 * 1024));
+    assert_eq!(parse_file_size("1 GiB"), Ok(1024 * 1024 * 1024));
+    assert!(parse_file_size("").is_err());
+    assert!(parse_file_size("MB").is_err());
+    assert!(parse_file_size("1.5MB").is_err());
+    assert!(parse_file_size("10XB").is_err());
+    assert!(parse_file_size("99999999999999999999GB").is_err());
+}
+
+#[test]
+fn parse_and_resolve_the_number_of_jobs() {
+    assert_eq!(parse_jobs("0"), Ok(0));
+    assert_eq!(parse_jobs("4"), Ok(4));
+    assert!(parse_jobs("-2").unwrap_err().contains("can't be negative"));
+    assert!(parse_jobs("four")
+        .unwrap_err()
+        .starts_with("Invalid number of jobs"));
+
+    assert_eq!(resolve_jobs(Some(3)), 3);
+    assert_eq!(resolve_jobs(Some(0)), resolve_jobs(None));
+    assert!(resolve_jobs(None) >= 1);
+
+    let parse_args =
+        |jobs: &str| LinemanArgs::from_iter_safe(["lineman", "-p", ".", "--jobs", jobs]);
+
+    assert_eq!(parse_args("0").unwrap().jobs, Some(0));
+    assert!(parse_args("-1")
+        .unwrap_err()
+        .message
+        .contains("can't be negative"));
+    assert_eq!(
+        LinemanArgs::from_iter(["lineman", "-p", ".", "--threads", "2"]).jobs,
+        Some(2)
+    );
+}
+
+#[test]
+fn clean_file_skips_files_larger_than_the_maximum_size() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("file.txt");
+    fs::write(&path, "dirty \n").unwrap();
+
+    let read_options = |max_file_size| ReadOptions {
+        max_file_size: Some(max_file_size),
+        ..ReadOptions::default()
+    };
+
+    assert!(matches!(
+        clean_file(&path, &CleanOptions::default(), &read_options(7)),
+        Ok(cleaned_file) if cleaned_file.is_changed
+    ));
+    assert!(matches!(
+        clean_file(&path, &CleanOptions::default(), &read_options(6)),
+        Err(LinemanFileError::FileTooLarge(7))
+    ));
+}
+
+#[test]
+fn command_line_arguments_take_precedence_over_the_config() {
+    let config = || Config {
+        extensions: Some(vec!["py".to_string()]),
+        exclude: Some(vec!["vendor/**".to_string()]),
+        line_ending: Some(LineEnding::Crlf),
+        normalize_eof_newlines: Some(false),
+    };
+
+    let mut args = LinemanArgs::from_iter(["lineman", "-p", "."]);
+    args.apply_config(config());
+
+    assert_eq!(args.extensions, Some(vec!["py".to_string()]));
+    assert_eq!(args.exclude, vec!["vendor/**".to_string()]);
+    assert_eq!(args.line_ending, Some(LineEnding::Crlf));
+    assert!(args.disable_eof_newline_normalization);
+
+    let mut args = LinemanArgs::from_iter([
+        "lineman",
+        "-p",
+        ".",
+        "-e",
+        "rs",
+        "--exclude",
+        "target/**",
+        "--line-ending",
+        "lf",
+    ]);
+    args.apply_config(config());
+
+    assert_eq!(args.extensions, Some(vec!["rs".to_string()]));
+    assert_eq!(args.exclude, vec!["target/**".to_string()]);
+    assert_eq!(args.line_ending, Some(LineEnding::Lf));
+}
+
+#[test]
+fn split_comma_separated_extensions() {
+    let mut args =
+        LinemanArgs::from_iter(["lineman", "-p", ".", "-e", ".rs,py", "md,", "-e", "toml"]);
+    args.normalize_extensions();
+
+    assert_eq!(
+        args.extensions,
+        Some(vec![
+            "rs".to_string(),
+            "py".to_string(),
+            "md".to_string(),
+            "toml".to_string()
+        ])
+    );
+}
+
+#[test]
+fn select_files_by_extension_given_with_a_leading_dot() {
+    let filter = test_filter(&["-e", ".rs", "..py"]);
+    let is_selected = |path: &str| filter.matches(Path::new(path), Path::new(path));
+
+    assert!(is_selected("src/main.rs"));
+    // Only a single leading dot is stripped
+    assert!(!is_selected("main.py"));
+}
+
+#[test]
+fn run_cleans_the_selected_files() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("main.rs");
+    let unselected_path = temp_dir.path().join("notes.txt");
+    fs::write(&path, "fn main() {}   \n\n").unwrap();
+    fs::write(&unselected_path, "notes   \n").unwrap();
+
+    let args = LinemanArgs::from_iter([
+        OsStr::new("lineman"),
+        OsStr::new("-p"),
+        temp_dir.path().as_os_str(),
+        OsStr::new("-e"),
+        OsStr::new("rs"),
+        OsStr::new("--quiet"),
+    ]);
+    assert!(run(args).is_ok());
+
+    assert_eq!(fs::read_to_string(&path).unwrap(), "fn main() {}\n");
+    assert_eq!(fs::read_to_string(&unselected_path).unwrap(), "notes   \n");
+}
+
+#[test]
+fn clean_file_counts_the_bytes_saved() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("file.txt");
+    fs::write(&path, "first   \nsecond\t\nthird").unwrap();
+
+    let cleaned_file = clean_file(&path, &CleanOptions::default(), &ReadOptions::default())
+        .ok()
+        .unwrap();
+
+    assert_eq!(
+        cleaned_file.clean_stats,
+        CleanStats {
+            changed_line_count: 3,
+            original_byte_count: 22,
+            cleaned_byte_count: 19,
+        }
+    );
+    assert_eq!(cleaned_file.clean_stats.bytes_saved(), 3);
+}
+
+#[test]
+fn diff_file_shows_cleaned_lines_with_context() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("file.txt");
+    fs::write(&path, "one\ntwo  \nthree\nfour\nfive\n").unwrap();
+
+    let cleaned_file = clean_file(&path, &CleanOptions::default(), &ReadOptions::default())
+        .ok()
+        .unwrap();
+    let diff = diff_file(Path::new("file.txt"), &cleaned_file, 1);
+
+    assert_eq!(
+        diff,
+        "--- a/file.txt\n+++ b/file.txt\n@@ -1,3 +1,3 @@\n one\n-two  \n+two\n three\n"
+    );
+}
+
+#[test]
+fn clean_file_counts_an_added_final_newline_as_one_changed_line() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("file.txt");
+    fs::write(&path, "first\nsecond\nthird").unwrap();
+
+    let cleaned_file = clean_file(&path, &CleanOptions::default(), &ReadOptions::default())
+        .ok()
+        .unwrap();
+
+    assert_eq!(cleaned_file.clean_stats.changed_line_count, 1);
+}
+
+#[test]
+fn stream_large_files_line_by_line() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("large.txt");
+    let line_count = STREAMING_FILE_SIZE as usize / "line\n".len() + 1;
+    fs::write(&path, "line   \n".repeat(line_count) + "\n\n").unwrap();
+
+    let process_options = test_process_options();
+
+    match stream_file(&path, &process_options.clean_options, &process_options) {
+        Ok(Some(ProcessedFile {
+            file_outcome: FileOutcome::Cleaned(clean_stats),
+            ..
+        })) => {
+            assert_eq!(clean_stats.changed_line_count, line_count + 2);
+            assert_eq!(clean_stats.bytes_saved(), line_count as i64 * 3 + 2);
+        }
+        _ => panic!("the file wasn't streamed"),
+    }
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "line\n".repeat(line_count)
+    );
+    assert!(matches!(
+        stream_file(&path, &process_options.clean_options, &process_options),
+        Ok(Some(ProcessedFile {
+            file_outcome: FileOutcome::AlreadyClean,
+            ..
+        }))
+    ));
+
+    let small_path = temp_dir.path().join("small.txt");
+    fs::write(&small_path, "line   \n").unwrap();
+
+    assert!(matches!(
+        stream_file(
+            &small_path,
+            &process_options.clean_options,
+            &process_options
+        ),
+        Ok(None)
+    ));
+}
+
+#[test]
+fn already_clean_files_are_never_rewritten() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("clean.txt");
+    fs::write(&path, "first\nsecond\n").unwrap();
+
+    let old_mtime = FileTime::from_unix_time(1_000_000_000, 0);
+    filetime::set_file_mtime(&path, old_mtime).unwrap();
+
+    let process_options = ProcessOptions {
+        write_options: WriteOptions {
+            backup: true,
+            ..WriteOptions::default()
+        },
+        ..test_process_options()
+    };
+
+    assert!(matches!(
+        clean_and_write_file(&path, &process_options).file_outcome,
+        FileOutcome::AlreadyClean
+    ));
+    assert_eq!(
+        FileTime::from_last_modification_time(&fs::metadata(&path).unwrap()),
+        old_mtime
+    );
+    assert!(!temp_dir.path().join("clean.txt.bak").exists());
+}
+
+#[test]
+fn prompt_until_a_valid_confirmation_is_read() {
+    let clean_stats = CleanStats {
+        changed_line_count: 1,
+        original_byte_count: 2,
+        cleaned_byte_count: 1,
+    };
+    let prompt =
+        |input: &str| prompt_confirmation(&mut input.as_bytes(), Path::new("a.rs"), &clean_stats);
+
+    assert_eq!(prompt("y\n"), Confirmation::Yes);
+    assert_eq!(prompt("No\n"), Confirmation::No);
+    assert_eq!(prompt("maybe\na\n"), Confirmation::All);
+    assert_eq!(prompt("q\n"), Confirmation::Quit);
+    assert_eq!(prompt(""), Confirmation::Quit);
+}
+
+#[test]
+fn generate_completions_for_every_flag() {
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish, Shell::PowerShell] {
+        let mut completions = Vec::new();
+        LinemanArgs::clap().gen_completions_to("lineman", shell, &mut completions);
+        let completions = String::from_utf8(completions).unwrap();
+
+        assert!(completions.contains("check"));
+        assert!(completions.contains("max-line-length"));
+    }
+}
+
+#[test]
+fn select_and_exclude_files_by_regex() {
+    let filter = test_filter(&[
+        "-e",
+        "py",
+        "--include-regex",
+        "/scripts/[^/]+$",
+        "--exclude-regex",
+        r"_test\.py$",
+    ]);
+    let is_selected =
+        |path: &str, relative_path: &str| filter.matches(Path::new(path), Path::new(relative_path));
+
+    assert!(is_selected("/repo/scripts/deploy", "scripts/deploy"));
+    assert!(is_selected("/repo/src/main.py", "src/main.py"));
+    assert!(!is_selected("/repo/src/main_test.py", "src/main_test.py"));
+    assert!(!is_selected("/repo/src/scripts.rs", "src/scripts.rs"));
+
+    assert_eq!(
+        PathPatterns::new(&[], &["(unclosed".to_string()])
+            .err()
+            .unwrap()
+            .exit_code(),
+        2
+    );
+}
+
+#[test]
+fn clean_cr_separated_files_line_by_line() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("legacy.txt");
+    fs::write(&path, "first line  \rsecond line\t\rthird line\r\r").unwrap();
+
+    let cleaned_file = clean_file(&path, &CleanOptions::default(), &ReadOptions::default())
+        .ok()
+        .unwrap();
+
+    assert_eq!(
+        cleaned_file.lines,
+        vec!["first line\r", "second line\r", "third line\r"]
+    );
+    assert_eq!(cleaned_file.clean_stats.changed_line_count, 3);
+}
+
+#[test]
+fn check_lists_files_missing_a_final_newline_without_changing_them() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("add_newline_to_end_of_file.py");
+    let content =
+        "def main():\n    print(\"Hello World\")\n\nif __name__ == \"__main__\":\n    main()";
+    let trailing_whitespace_path = temp_dir.path().join("trailing_whitespace.py");
+    fs::write(&path, content).unwrap();
+    fs::write(&trailing_whitespace_path, "main()   \n").unwrap();
+
+    let process_options = ProcessOptions {
+        write_changes: false,
+        ..test_process_options()
+    };
+    let mut report = Report::default();
+
+    for path in [&path, &trailing_whitespace_path] {
+        let processed_file = process_file(path, &process_options, &ProgressBar::hidden());
+        record_processed_file(&mut report, path.clone(), processed_file);
+    }
+
+    assert_eq!(report.missing_final_newline_file_paths, vec![path.clone()]);
+    assert_eq!(report.would_be_cleaned_files.len(), 2);
+    assert_eq!(report.exit_code(true), 1);
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+}
+
+#[test]
+fn warn_about_mixed_line_endings_unless_a_line_ending_is_set() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("mixed.bat");
+    let content = "@echo off\r\necho Hello\necho World\r\n";
+    fs::write(&path, content).unwrap();
+
+    let mut process_options = ProcessOptions {
+        write_changes: false,
+        ..test_process_options()
+    };
+    let mut report = Report::default();
+    let processed_file = process_file(&path, &process_options, &ProgressBar::hidden());
+    record_processed_file(&mut report, path.clone(), processed_file);
+
+    assert_eq!(report.mixed_line_ending_file_paths, vec![path.clone()]);
+    assert_eq!(report.would_be_cleaned_files.len(), 1);
+    assert_eq!(fs::read_to_string(&path).unwrap(), content);
+
+    process_options.write_changes = true;
+    process_options.clean_options.line_ending = Some(LineEnding::Crlf);
+    let mut report = Report::default();
+    let processed_file = process_file(&path, &process_options, &ProgressBar::hidden());
+    record_processed_file(&mut report, path.clone(), processed_file);
+
+    assert!(report.mixed_line_ending_file_paths.is_empty());
+    assert_eq!(report.cleaned_files.len(), 1);
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "@echo off\r\necho Hello\r\necho World\r\n"
+    );
+}
+
+#[test]
+fn files_removed_after_being_selected_are_reported_as_vanished() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    fs::write(root_path.join("kept.rs"), "fn kept() {}   \n").unwrap();
+    fs::write(root_path.join("removed.rs"), "fn removed() {}   \n").unwrap();
+
+    let process_options = ProcessOptions {
+        fail_fast: true,
+        ..test_process_options()
+    };
+    let mut report = Report::default();
+    let file_paths = select_file_paths(
+        root_path,
+        &test_filter(&["-e", "rs"]),
+        &mut HashSet::new(),
+        &mut report,
+    );
+    assert_eq!(file_paths.len(), 2);
+
+    // Something else removes the file between the walk and the cleaning
+    fs::remove_file(root_path.join("removed.rs")).unwrap();
+    report.merge(process_paths(file_paths, &process_options, &ProgressBar::hidden()).unwrap());
+
+    assert_eq!(
+        report.vanished_file_paths,
+        vec![root_path.join("removed.rs")]
+    );
+    assert_eq!(report.cleaned_files.len(), 1);
+    assert!(report.unreadable_files.is_empty());
+    assert_eq!(report.exit_code(false), 0);
 }
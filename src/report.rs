@@ -0,0 +1,1400 @@
+use lineman::{Issue, LongLine};
+use owo_colors::Style;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use walkdir::Error;
+
+/// How the report is printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportFormat {
+    Text,
+    Json,
+    /// GitHub Actions workflow commands, which annotate the lines of a pull request
+    Github,
+    /// A SARIF 2.1.0 log, which code scanning dashboards can import
+    Sarif,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(report_format: &str) -> Result<Self, Self::Err> {
+        match report_format.to_lowercase().as_str() {
+            "text" => Ok(ReportFormat::Text),
+            "json" => Ok(ReportFormat::Json),
+            "github" => Ok(ReportFormat::Github),
+            "sarif" => Ok(ReportFormat::Sarif),
+            _ => Err(format!("Unknown report format: {}", report_format)),
+        }
+    }
+}
+
+/// How much cleaning a file changed it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CleanStats {
+    pub changed_line_count: usize,
+    pub original_byte_count: usize,
+    pub cleaned_byte_count: usize,
+}
+
+impl CleanStats {
+    /// The number of bytes cleaning removed, which is negative when it added more than it removed (such as a final
+    /// newline or CRLF line endings)
+    pub fn bytes_saved(&self) -> i64 {
+        self.original_byte_count as i64 - self.cleaned_byte_count as i64
+    }
+}
+
+impl fmt::Display for CleanStats {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            formatter,
+            "{} line(s) changed, {} byte(s) saved",
+            self.changed_line_count,
+            self.bytes_saved()
+        )
+    }
+}
+
+/// The outcome of every file processed during a run
+#[derive(Default)]
+pub struct Report {
+    pub already_clean_file_paths: Vec<PathBuf>,
+    pub cleaned_files: Vec<(PathBuf, CleanStats)>,
+    pub would_be_cleaned_files: Vec<(PathBuf, CleanStats)>,
+    pub unreadable_files: Vec<(PathBuf, io::Error)>,
+    pub unwritable_files: Vec<(PathBuf, io::Error)>,
+    pub read_only_files: Vec<(PathBuf, io::Error)>,
+    pub not_backed_up_files: Vec<(PathBuf, io::Error)>,
+    pub binary_file_paths: Vec<PathBuf>,
+    pub oversized_files: Vec<(PathBuf, u64)>,
+    pub missing_file_paths: Vec<PathBuf>,
+    /// Files that were selected but no longer existed by the time they were opened
+    pub vanished_file_paths: Vec<PathBuf>,
+    pub walk_dir_errors: Vec<Error>,
+    pub long_line_files: Vec<(PathBuf, Vec<LongLine>)>,
+    pub issue_files: Vec<(PathBuf, Vec<Issue>)>,
+    /// Files whose last line had no newline, which cleaning adds
+    pub missing_final_newline_file_paths: Vec<PathBuf>,
+    /// Files that end their lines in more than one style while no line ending is set, whose lines are converted to
+    /// their most common one
+    pub mixed_line_ending_file_paths: Vec<PathBuf>,
+    /// Files that were reached again through another root path or a symlink, and so weren't processed a second time
+    pub duplicate_file_count: usize,
+}
+
+impl Report {
+    pub fn record(&mut self, path: PathBuf, file_outcome: FileOutcome) {
+        match file_outcome {
+            FileOutcome::AlreadyClean => self.already_clean_file_paths.push(path),
+            FileOutcome::Cleaned(clean_stats) => self.cleaned_files.push((path, clean_stats)),
+            FileOutcome::WouldBeCleaned(clean_stats) => {
+                self.would_be_cleaned_files.push((path, clean_stats))
+            }
+            FileOutcome::NotRead(io_error) => self.unreadable_files.push((path, io_error)),
+            FileOutcome::NotWritten(io_error) => self.unwritable_files.push((path, io_error)),
+            FileOutcome::ReadOnly(io_error) => self.read_only_files.push((path, io_error)),
+            FileOutcome::NotBackedUp(io_error) => self.not_backed_up_files.push((path, io_error)),
+            FileOutcome::Binary => self.binary_file_paths.push(path),
+            FileOutcome::TooLarge(file_size) => self.oversized_files.push((path, file_size)),
+            FileOutcome::Vanished => self.vanished_file_paths.push(path),
+        }
+    }
+
+    /// Adds every outcome from another report after the ones already recorded
+    pub fn merge(&mut self, other: Report) {
+        let Report {
+            already_clean_file_paths,
+            cleaned_files,
+            would_be_cleaned_files,
+            unreadable_files,
+            unwritable_files,
+            read_only_files,
+            not_backed_up_files,
+            binary_file_paths,
+            oversized_files,
+            missing_file_paths,
+            vanished_file_paths,
+            walk_dir_errors,
+            long_line_files,
+            issue_files,
+            missing_final_newline_file_paths,
+            mixed_line_ending_file_paths,
+            duplicate_file_count,
+        } = other;
+
+        self.already_clean_file_paths
+            .extend(already_clean_file_paths);
+        self.cleaned_files.extend(cleaned_files);
+        self.would_be_cleaned_files.extend(would_be_cleaned_files);
+        self.unreadable_files.extend(unreadable_files);
+        self.unwritable_files.extend(unwritable_files);
+        self.read_only_files.extend(read_only_files);
+        self.not_backed_up_files.extend(not_backed_up_files);
+        self.binary_file_paths.extend(binary_file_paths);
+        self.oversized_files.extend(oversized_files);
+        self.missing_file_paths.extend(missing_file_paths);
+        self.vanished_file_paths.extend(vanished_file_paths);
+        self.walk_dir_errors.extend(walk_dir_errors);
+        self.long_line_files.extend(long_line_files);
+        self.issue_files.extend(issue_files);
+        self.missing_final_newline_file_paths
+            .extend(missing_final_newline_file_paths);
+        self.mixed_line_ending_file_paths
+            .extend(mixed_line_ending_file_paths);
+        self.duplicate_file_count += duplicate_file_count;
+    }
+
+    /// The bytes saved across every file that was, or would be, cleaned
+    pub fn total_bytes_saved(&self) -> i64 {
+        self.cleaned_files
+            .iter()
+            .chain(&self.would_be_cleaned_files)
+            .map(|(_, clean_stats)| clean_stats.bytes_saved())
+            .sum()
+    }
+
+    /// Rewrites every file path relative to the root path it was found under, picking the deepest root when several
+    /// contain it. Paths that no root contains, other than as the root itself, are kept as they are
+    pub fn relativize_paths(&mut self, root_paths: &[PathBuf]) {
+        let relativize = |path: &mut PathBuf| {
+            let relative_path = root_paths
+                .iter()
+                .filter_map(|root_path| path.strip_prefix(root_path).ok())
+                .filter(|relative_path| !relative_path.as_os_str().is_empty())
+                .min_by_key(|relative_path| relative_path.components().count());
+
+            if let Some(relative_path) = relative_path {
+                *path = relative_path.to_path_buf();
+            }
+        };
+
+        self.already_clean_file_paths
+            .iter_mut()
+            .chain(&mut self.binary_file_paths)
+            .chain(&mut self.missing_file_paths)
+            .chain(&mut self.vanished_file_paths)
+            .chain(&mut self.missing_final_newline_file_paths)
+            .chain(&mut self.mixed_line_ending_file_paths)
+            .for_each(relativize);
+        self.cleaned_files
+            .iter_mut()
+            .chain(&mut self.would_be_cleaned_files)
+            .for_each(|(path, _)| relativize(path));
+        self.unreadable_files
+            .iter_mut()
+            .chain(&mut self.unwritable_files)
+            .chain(&mut self.read_only_files)
+            .chain(&mut self.not_backed_up_files)
+            .for_each(|(path, _)| relativize(path));
+        self.oversized_files
+            .iter_mut()
+            .for_each(|(path, _)| relativize(path));
+        self.long_line_files
+            .iter_mut()
+            .for_each(|(path, _)| relativize(path));
+        self.issue_files
+            .iter_mut()
+            .for_each(|(path, _)| relativize(path));
+    }
+
+    /// Whether any file was, or would be, cleaned
+    pub fn has_changes(&self) -> bool {
+        !self.cleaned_files.is_empty() || !self.would_be_cleaned_files.is_empty()
+    }
+
+    /// Whether every file was already clean, leaving nothing to list besides them
+    pub fn is_all_clean(&self) -> bool {
+        !self.has_changes()
+            && self.skipped_file_count() == 0
+            && self.error_count() == 0
+            && self.long_line_files.is_empty()
+            && self.mixed_line_ending_file_paths.is_empty()
+            && self.duplicate_file_count == 0
+    }
+
+    /// Files skipped on purpose, or because they were removed during the run, as opposed to because of an error
+    pub fn skipped_file_count(&self) -> usize {
+        self.binary_file_paths.len() + self.oversized_files.len() + self.vanished_file_paths.len()
+    }
+
+    /// Files that couldn't be processed due to an error or that didn't exist, along with directories that couldn't be
+    /// walked
+    pub fn error_count(&self) -> usize {
+        self.unreadable_files.len()
+            + self.unwritable_files.len()
+            + self.read_only_files.len()
+            + self.not_backed_up_files.len()
+            + self.missing_file_paths.len()
+            + self.walk_dir_errors.len()
+    }
+
+    /// Files that were skipped due to errors, or that didn't exist, which fail the run
+    pub fn failed_file_count(&self) -> usize {
+        self.unreadable_files.len()
+            + self.unwritable_files.len()
+            + self.read_only_files.len()
+            + self.not_backed_up_files.len()
+            + self.missing_file_paths.len()
+    }
+
+    /// The status a run that produced this report exits with. Files skipped due to errors take precedence over walkdir
+    /// errors, which take precedence over files that would be cleaned during a check
+    pub fn exit_code(&self, check: bool) -> i32 {
+        if self.failed_file_count() > 0 {
+            5
+        } else if !self.walk_dir_errors.is_empty() {
+            6
+        } else if check && self.has_changes() {
+            1
+        } else {
+            0
+        }
+    }
+
+    pub fn print(&self, report_format: ReportFormat, use_color: bool) {
+        match report_format {
+            ReportFormat::Text => print_text_report(self, &Palette::new(use_color)),
+            ReportFormat::Json => println!("{}", json_report(self)),
+            ReportFormat::Sarif => println!("{}", sarif_report(self)),
+            ReportFormat::Github => {
+                for annotation in github_annotations(self) {
+                    println!("{}", annotation);
+                }
+            }
+        }
+    }
+
+    /// Prints the totals for the run without listing any files. Only text and JSON are supported, which is checked
+    /// before the run
+    pub fn print_stats(&self, report_format: ReportFormat) {
+        match report_format {
+            ReportFormat::Json => println!("{}", json_stats(self)),
+            _ => print_text_stats(self),
+        }
+    }
+
+    /// Every file that was processed, whatever happened to it. Missing files were never found, so they aren't counted
+    pub fn file_count(&self) -> usize {
+        self.already_clean_file_paths.len()
+            + self.cleaned_files.len()
+            + self.would_be_cleaned_files.len()
+            + self.binary_file_paths.len()
+            + self.oversized_files.len()
+            + self.vanished_file_paths.len()
+            + self.unreadable_files.len()
+            + self.unwritable_files.len()
+            + self.read_only_files.len()
+            + self.not_backed_up_files.len()
+    }
+
+    /// The lines changed across every file that was, or would be, cleaned
+    pub fn total_changed_line_count(&self) -> usize {
+        self.cleaned_files
+            .iter()
+            .chain(&self.would_be_cleaned_files)
+            .map(|(_, clean_stats)| clean_stats.changed_line_count)
+            .sum()
+    }
+
+    /// A one-line count of every outcome, such as "Cleaned 42, already clean 310, skipped 3, errors 1, 1.2KB
+    /// removed."
+    pub fn summary(&self) -> String {
+        let mut counts = vec![format!("Cleaned {}", self.cleaned_files.len())];
+
+        if !self.would_be_cleaned_files.is_empty() {
+            counts.push(format!(
+                "would be cleaned {}",
+                self.would_be_cleaned_files.len()
+            ));
+        }
+
+        counts.push(format!(
+            "already clean {}",
+            self.already_clean_file_paths.len()
+        ));
+        counts.push(format!("skipped {}", self.skipped_file_count()));
+        counts.push(format!("errors {}", self.error_count()));
+
+        let bytes_saved = self.total_bytes_saved();
+
+        if bytes_saved < 0 {
+            counts.push(format!(
+                "{} added",
+                format_byte_count(bytes_saved.unsigned_abs())
+            ));
+        } else {
+            counts.push(format!(
+                "{} removed",
+                format_byte_count(bytes_saved.unsigned_abs())
+            ));
+        }
+
+        format!("{}.", counts.join(", "))
+    }
+
+    /// The counts of `summary` as a single line of JSON, such as
+    /// `{"cleaned":42,"skipped":3,"errors":1,"bytes_removed":1234}`. Like the summary, and unlike the JSON report's
+    /// counts, `skipped` leaves out the files counted in `errors`
+    pub fn summary_json(&self) -> String {
+        let summary_json = SummaryJson {
+            cleaned: self.cleaned_files.len() + self.would_be_cleaned_files.len(),
+            skipped: self.skipped_file_count(),
+            errors: self.error_count(),
+            bytes_removed: self.total_bytes_saved(),
+        };
+
+        serde_json::to_string(&summary_json).expect("the summary is always serializable")
+    }
+}
+
+/// The fields of `--summary-json`, which downstream parsers rely on, so they are only ever added to. They are
+/// serialized in this order
+#[derive(Serialize)]
+struct SummaryJson {
+    /// Files that were cleaned, or that would be cleaned when nothing is written
+    cleaned: usize,
+    skipped: usize,
+    errors: usize,
+    /// Negative when cleaning adds more bytes than it removes
+    bytes_removed: i64,
+}
+
+/// Formats a byte count with the largest unit it reaches, to one decimal place past bytes
+fn format_byte_count(byte_count: u64) -> String {
+    const UNITS: [&str; 3] = ["KB", "MB", "GB"];
+
+    if byte_count < 1024 {
+        return format!("{}B", byte_count);
+    }
+
+    let mut size = byte_count as f64 / 1024.0;
+    let mut unit_index = 0;
+
+    while size >= 1024.0 && unit_index < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit_index += 1;
+    }
+
+    format!("{:.1}{}", size, UNITS[unit_index])
+}
+
+pub enum FileOutcome {
+    AlreadyClean,
+    Cleaned(CleanStats),
+    WouldBeCleaned(CleanStats),
+    NotRead(io::Error),
+    NotWritten(io::Error),
+    ReadOnly(io::Error),
+    NotBackedUp(io::Error),
+    Binary,
+    TooLarge(u64),
+    /// The file was removed after it was selected, which is a race with whatever removed it rather than a failure
+    Vanished,
+}
+
+impl FileOutcome {
+    /// Whether the file was skipped due to an error, as opposed to being skipped on purpose
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            FileOutcome::NotRead(_)
+                | FileOutcome::NotWritten(_)
+                | FileOutcome::ReadOnly(_)
+                | FileOutcome::NotBackedUp(_)
+        )
+    }
+}
+
+impl fmt::Display for FileOutcome {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FileOutcome::AlreadyClean => write!(formatter, "already clean"),
+            FileOutcome::Cleaned(clean_stats) => write!(formatter, "cleaned ({})", clean_stats),
+            FileOutcome::WouldBeCleaned(clean_stats) => {
+                write!(formatter, "would be cleaned ({})", clean_stats)
+            }
+            FileOutcome::NotRead(io_error) => {
+                write!(formatter, "skipped, could not be read ({})", io_error)
+            }
+            FileOutcome::NotWritten(io_error) => {
+                write!(formatter, "skipped, could not be written ({})", io_error)
+            }
+            FileOutcome::ReadOnly(io_error) => write!(formatter, "skipped, {}", io_error),
+            FileOutcome::NotBackedUp(io_error) => {
+                write!(formatter, "not backed up, left unchanged ({})", io_error)
+            }
+            FileOutcome::Binary => write!(formatter, "skipped binary file"),
+            FileOutcome::TooLarge(file_size) => {
+                write!(formatter, "skipped oversized file ({} bytes)", file_size)
+            }
+            FileOutcome::Vanished => write!(formatter, "skipped, removed during the run"),
+        }
+    }
+}
+
+/// The kinds of errors that can happen while walking directories, each of which calls for a different fix
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WalkDirErrorKind {
+    PermissionDenied,
+    SymlinkLoop,
+    Other,
+}
+
+impl WalkDirErrorKind {
+    pub fn of(walk_dir_error: &Error) -> Self {
+        if walk_dir_error.loop_ancestor().is_some() {
+            WalkDirErrorKind::SymlinkLoop
+        } else if walk_dir_error.io_error().map(io::Error::kind)
+            == Some(io::ErrorKind::PermissionDenied)
+        {
+            WalkDirErrorKind::PermissionDenied
+        } else {
+            WalkDirErrorKind::Other
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WalkDirErrorKind::PermissionDenied => "permission_denied",
+            WalkDirErrorKind::SymlinkLoop => "symlink_loop",
+            WalkDirErrorKind::Other => "other",
+        }
+    }
+}
+
+/// Explains a walkdir error in terms of what went wrong and where, rather than walkdir's own message
+fn describe_walk_dir_error(walk_dir_error: &Error) -> String {
+    let path = walk_dir_error.path().map_or_else(
+        || "an unknown path".to_string(),
+        |path| path.display().to_string(),
+    );
+
+    match WalkDirErrorKind::of(walk_dir_error) {
+        WalkDirErrorKind::PermissionDenied => format!(
+            "Permission denied: {} couldn't be read, so nothing inside it was processed",
+            path
+        ),
+        // Loops can only be detected while following symlinks
+        WalkDirErrorKind::SymlinkLoop => format!(
+            "Symlink loop (following symlinks): {} points to its ancestor {}",
+            path,
+            walk_dir_error
+                .loop_ancestor()
+                .map_or_else(String::new, |loop_ancestor| loop_ancestor
+                    .display()
+                    .to_string())
+        ),
+        WalkDirErrorKind::Other => match walk_dir_error.io_error() {
+            Some(io_error) => format!(
+                "{} (at depth {}) couldn't be walked: {}",
+                path,
+                walk_dir_error.depth(),
+                io_error
+            ),
+            None => walk_dir_error.to_string(),
+        },
+    }
+}
+
+/// Whether the text report is colored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Resolves the choice for stdout, where `auto` only colors output going to a terminal, and never while the
+    /// `NO_COLOR` environment variable is set to a non-empty value
+    pub fn use_color(&self) -> bool {
+        self.resolve(env::var_os("NO_COLOR"), io::stdout().is_terminal())
+    }
+
+    fn resolve(&self, no_color: Option<OsString>, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Auto => is_terminal && no_color.is_none_or(|no_color| no_color.is_empty()),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(color_choice: &str) -> Result<Self, Self::Err> {
+        match color_choice.to_lowercase().as_str() {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("Unknown color choice: {}", color_choice)),
+        }
+    }
+}
+
+/// The styles used for each kind of entry in the text report, which are all plain when color is disabled
+struct Palette {
+    cleaned: Style,
+    warning: Style,
+    error: Style,
+}
+
+impl Palette {
+    fn new(use_color: bool) -> Self {
+        if use_color {
+            Palette {
+                cleaned: Style::new().green(),
+                warning: Style::new().yellow(),
+                error: Style::new().red(),
+            }
+        } else {
+            Palette {
+                cleaned: Style::new(),
+                warning: Style::new(),
+                error: Style::new(),
+            }
+        }
+    }
+}
+
+/// Prints each section of the report that has entries. A routine run where every file was already clean only prints
+/// a single line
+fn print_text_report(report: &Report, palette: &Palette) {
+    let indent = " ".repeat(4);
+
+    if report.is_all_clean() {
+        println!("{}", palette.cleaned.style("All files clean"));
+        return;
+    }
+
+    if !report.cleaned_files.is_empty() {
+        println!("Cleaned Files:");
+
+        for (cleaned_file_path, clean_stats) in &report.cleaned_files {
+            println!(
+                "{}{} ({})",
+                indent,
+                palette.cleaned.style(cleaned_file_path.display()),
+                clean_stats
+            );
+        }
+    }
+
+    if !report.would_be_cleaned_files.is_empty() {
+        println!("Files That Would Be Cleaned:");
+
+        for (would_be_cleaned_file_path, clean_stats) in &report.would_be_cleaned_files {
+            println!(
+                "{}{} ({})",
+                indent,
+                palette.warning.style(would_be_cleaned_file_path.display()),
+                clean_stats
+            );
+        }
+    }
+
+    if !report.missing_final_newline_file_paths.is_empty() {
+        println!("Missing Final Newline:");
+
+        for missing_final_newline_file_path in &report.missing_final_newline_file_paths {
+            println!(
+                "{}{}",
+                indent,
+                palette
+                    .warning
+                    .style(missing_final_newline_file_path.display())
+            );
+        }
+    }
+
+    // Clean files are the common case, so they are only counted rather than listed
+    if !report.already_clean_file_paths.is_empty() {
+        println!(
+            "Already Clean: {} file(s)",
+            report.already_clean_file_paths.len()
+        );
+    }
+
+    if report.duplicate_file_count > 0 {
+        println!(
+            "Duplicates Skipped: {} file(s) reached more than once",
+            report.duplicate_file_count
+        );
+    }
+
+    if !report.unreadable_files.is_empty() {
+        println!("Skipped Files (Could Not Read):");
+
+        for (unreadable_file_path, io_error) in &report.unreadable_files {
+            println!(
+                "{}{} ({})",
+                indent,
+                palette.error.style(unreadable_file_path.display()),
+                io_error
+            );
+        }
+    }
+
+    if !report.unwritable_files.is_empty() {
+        println!("Skipped Files (Could Not Write):");
+
+        for (unwritable_file_path, io_error) in &report.unwritable_files {
+            println!(
+                "{}{} ({})",
+                indent,
+                palette.error.style(unwritable_file_path.display()),
+                io_error
+            );
+        }
+    }
+
+    if !report.read_only_files.is_empty() {
+        println!("Skipped Files (Read-Only):");
+
+        for (read_only_file_path, io_error) in &report.read_only_files {
+            println!(
+                "{}{} ({})",
+                indent,
+                palette.error.style(read_only_file_path.display()),
+                io_error
+            );
+        }
+    }
+
+    if !report.not_backed_up_files.is_empty() {
+        println!("Files Not Backed Up (Left Unchanged):");
+
+        for (not_backed_up_file_path, io_error) in &report.not_backed_up_files {
+            println!(
+                "{}{} ({})",
+                indent,
+                palette.error.style(not_backed_up_file_path.display()),
+                io_error
+            );
+        }
+    }
+
+    if !report.binary_file_paths.is_empty() {
+        println!("Skipped Binary Files:");
+
+        for binary_file_path in &report.binary_file_paths {
+            println!(
+                "{}{}",
+                indent,
+                palette.warning.style(binary_file_path.display())
+            );
+        }
+    }
+
+    if !report.oversized_files.is_empty() {
+        println!("Skipped Oversized Files:");
+
+        for (oversized_file_path, file_size) in &report.oversized_files {
+            println!(
+                "{}{} ({} bytes)",
+                indent,
+                palette.warning.style(oversized_file_path.display()),
+                file_size
+            );
+        }
+    }
+
+    if !report.missing_file_paths.is_empty() {
+        println!("Skipped Missing Files (Not Found):");
+
+        for missing_file_path in &report.missing_file_paths {
+            println!(
+                "{}{}",
+                indent,
+                palette.error.style(missing_file_path.display())
+            );
+        }
+    }
+
+    if !report.vanished_file_paths.is_empty() {
+        println!("Skipped Vanished Files (Removed During The Run):");
+
+        for vanished_file_path in &report.vanished_file_paths {
+            println!(
+                "{}{}",
+                indent,
+                palette.warning.style(vanished_file_path.display())
+            );
+        }
+    }
+
+    if !report.mixed_line_ending_file_paths.is_empty() {
+        println!("Mixed Line Endings:");
+
+        for mixed_line_ending_file_path in &report.mixed_line_ending_file_paths {
+            println!(
+                "{}{}",
+                indent,
+                palette.warning.style(mixed_line_ending_file_path.display())
+            );
+        }
+    }
+
+    if !report.long_line_files.is_empty() {
+        println!("Lines Over The Maximum Length:");
+
+        for (long_line_file_path, long_lines) in &report.long_line_files {
+            for long_line in long_lines {
+                println!(
+                    "{}{} ({} characters)",
+                    indent,
+                    palette.warning.style(format!(
+                        "{}:{}",
+                        long_line_file_path.display(),
+                        long_line.line_number
+                    )),
+                    long_line.length
+                );
+            }
+        }
+    }
+
+    if !report.walk_dir_errors.is_empty() {
+        println!("Walkdir Errors:");
+
+        for walk_dir_error in &report.walk_dir_errors {
+            println!(
+                "{}{}",
+                indent,
+                palette.error.style(describe_walk_dir_error(walk_dir_error))
+            );
+        }
+    }
+
+    println!("{}", report.summary());
+}
+
+fn print_text_stats(report: &Report) {
+    println!("Files: {}", report.file_count());
+    println!(
+        "Changed: {} file(s), {} line(s)",
+        report.cleaned_files.len() + report.would_be_cleaned_files.len(),
+        report.total_changed_line_count()
+    );
+    println!("Already Clean: {}", report.already_clean_file_paths.len());
+    println!("Skipped: {}", report.skipped_file_count());
+    println!("Errors: {}", report.error_count());
+    println!("Bytes Saved: {}", report.total_bytes_saved());
+}
+
+fn json_stats(report: &Report) -> Value {
+    json!({
+        "files": report.file_count(),
+        "changed_files": report.cleaned_files.len() + report.would_be_cleaned_files.len(),
+        "changed_lines": report.total_changed_line_count(),
+        "already_clean": report.already_clean_file_paths.len(),
+        "skipped": report.skipped_file_count(),
+        "errors": report.error_count(),
+        "bytes_saved": report.total_bytes_saved(),
+    })
+}
+
+/// Builds a GitHub Actions annotation for each issue and overly long line, and for each file or directory that
+/// couldn't be processed
+fn github_annotations(report: &Report) -> Vec<String> {
+    let mut annotations = Vec::new();
+
+    for (path, issues) in &report.issue_files {
+        for issue in issues {
+            annotations.push(format!(
+                "::warning file={},line={}::{}",
+                escape_github_property(&path.display().to_string()),
+                issue.line_number,
+                escape_github_data(issue.message)
+            ));
+        }
+    }
+
+    for (path, long_lines) in &report.long_line_files {
+        for long_line in long_lines {
+            annotations.push(format!(
+                "::warning file={},line={}::Line is {} characters long",
+                escape_github_property(&path.display().to_string()),
+                long_line.line_number,
+                long_line.length
+            ));
+        }
+    }
+
+    for path in &report.mixed_line_ending_file_paths {
+        annotations.push(format!(
+            "::warning file={}::Mixed line endings",
+            escape_github_property(&path.display().to_string())
+        ));
+    }
+
+    let file_errors = report
+        .unreadable_files
+        .iter()
+        .chain(&report.unwritable_files)
+        .chain(&report.read_only_files)
+        .chain(&report.not_backed_up_files)
+        .map(|(path, io_error)| (path.as_path(), io_error.to_string()))
+        .chain(
+            report
+                .missing_file_paths
+                .iter()
+                .map(|path| (path.as_path(), "File not found".to_string())),
+        );
+
+    annotations.extend(file_errors.map(|(path, message)| {
+        format!(
+            "::error file={}::{}",
+            escape_github_property(&path.display().to_string()),
+            escape_github_data(&message)
+        )
+    }));
+    annotations.extend(report.walk_dir_errors.iter().map(|walk_dir_error| {
+        format!(
+            "::error::{}",
+            escape_github_data(&describe_walk_dir_error(walk_dir_error))
+        )
+    }));
+
+    annotations
+}
+
+/// Escapes the message of a workflow command, which ends at the first line break
+fn escape_github_data(data: &str) -> String {
+    data.replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+/// Escapes a property of a workflow command, where commas and colons would otherwise end the value
+fn escape_github_property(property: &str) -> String {
+    escape_github_data(property)
+        .replace(':', "%3A")
+        .replace(',', "%2C")
+}
+
+/// The rule id given to overly long lines in SARIF logs, which aren't found by a cleaning rule
+const MAX_LINE_LENGTH_RULE_ID: &str = "max-line-length";
+
+/// Builds a minimal SARIF 2.1.0 log with a result for each issue and overly long line, and a reporting descriptor for
+/// each rule that produced a result
+fn sarif_report(report: &Report) -> Value {
+    let mut rules = BTreeMap::new();
+    let mut results = Vec::new();
+    let mut add_result = |path: &Path, line_number: usize, rule_id: &str, message: String| {
+        rules
+            .entry(rule_id.to_string())
+            .or_insert_with(|| message.clone());
+        results.push(json!({
+            "ruleId": rule_id,
+            "level": "warning",
+            "message": { "text": message },
+            "locations": [{
+                "physicalLocation": {
+                    "artifactLocation": {
+                        "uri": path.to_string_lossy().replace('\\', "/"),
+                    },
+                    "region": { "startLine": line_number },
+                },
+            }],
+        }));
+    };
+
+    for (path, issues) in &report.issue_files {
+        for issue in issues {
+            add_result(
+                path,
+                issue.line_number,
+                issue.rule_id,
+                issue.message.to_string(),
+            );
+        }
+    }
+
+    for (path, long_lines) in &report.long_line_files {
+        for long_line in long_lines {
+            add_result(
+                path,
+                long_line.line_number,
+                MAX_LINE_LENGTH_RULE_ID,
+                format!("Line is {} characters long", long_line.length),
+            );
+        }
+    }
+
+    let rules: Vec<Value> = rules
+        .into_iter()
+        .map(|(rule_id, description)| {
+            json!({
+                "id": rule_id,
+                "shortDescription": { "text": description },
+            })
+        })
+        .collect();
+
+    json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "lineman",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+fn json_report(report: &Report) -> Value {
+    let cleaned_files = |cleaned_files: &[(PathBuf, CleanStats)]| -> Vec<Value> {
+        cleaned_files
+            .iter()
+            .map(|(path, clean_stats)| {
+                json!({
+                    "path": path.display().to_string(),
+                    "changed_lines": clean_stats.changed_line_count,
+                    "bytes_saved": clean_stats.bytes_saved(),
+                })
+            })
+            .collect()
+    };
+    let skipped_file = |path: &PathBuf, reason: String| json!({ "path": path.display().to_string(), "reason": reason });
+    let skipped_files: Vec<Value> = report
+        .unreadable_files
+        .iter()
+        .map(|(path, io_error)| skipped_file(path, format!("could not be read: {}", io_error)))
+        .chain(report.unwritable_files.iter().map(|(path, io_error)| {
+            skipped_file(path, format!("could not be written: {}", io_error))
+        }))
+        .chain(report.read_only_files.iter().map(|(path, io_error)| {
+            skipped_file(path, format!("could not be written: {}", io_error))
+        }))
+        .chain(report.not_backed_up_files.iter().map(|(path, io_error)| {
+            skipped_file(path, format!("backup could not be created: {}", io_error))
+        }))
+        .chain(
+            report
+                .binary_file_paths
+                .iter()
+                .map(|path| skipped_file(path, "binary".to_string())),
+        )
+        .chain(report.oversized_files.iter().map(|(path, file_size)| {
+            skipped_file(
+                path,
+                format!("larger than the maximum file size: {} bytes", file_size),
+            )
+        }))
+        .chain(
+            report
+                .missing_file_paths
+                .iter()
+                .map(|path| skipped_file(path, "not found".to_string())),
+        )
+        .chain(
+            report
+                .vanished_file_paths
+                .iter()
+                .map(|path| skipped_file(path, "removed during the run".to_string())),
+        )
+        .collect();
+    let walk_dir_errors: Vec<Value> = report
+        .walk_dir_errors
+        .iter()
+        .map(|walk_dir_error| {
+            json!({
+                "kind": WalkDirErrorKind::of(walk_dir_error).as_str(),
+                "path": walk_dir_error.path().map(|path| path.display().to_string()),
+                "message": describe_walk_dir_error(walk_dir_error),
+            })
+        })
+        .collect();
+
+    let missing_final_newline: Vec<String> = report
+        .missing_final_newline_file_paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+    let mixed_line_endings: Vec<String> = report
+        .mixed_line_ending_file_paths
+        .iter()
+        .map(|path| path.display().to_string())
+        .collect();
+
+    let long_lines: Vec<Value> = report
+        .long_line_files
+        .iter()
+        .flat_map(|(path, long_lines)| {
+            long_lines.iter().map(move |long_line| {
+                json!({
+                    "path": path.display().to_string(),
+                    "line": long_line.line_number,
+                    "length": long_line.length,
+                })
+            })
+        })
+        .collect();
+
+    json!({
+        "counts": {
+            "already_clean": report.already_clean_file_paths.len(),
+            "cleaned": report.cleaned_files.len(),
+            "would_be_cleaned": report.would_be_cleaned_files.len(),
+            "skipped": skipped_files.len(),
+            "walk_dir_errors": walk_dir_errors.len(),
+            "bytes_saved": report.total_bytes_saved(),
+            "long_lines": long_lines.len(),
+            "duplicates": report.duplicate_file_count,
+            "missing_final_newline": missing_final_newline.len(),
+            "mixed_line_endings": mixed_line_endings.len(),
+        },
+        "cleaned": cleaned_files(&report.cleaned_files),
+        "would_be_cleaned": cleaned_files(&report.would_be_cleaned_files),
+        "skipped": skipped_files,
+        "walk_dir_errors": walk_dir_errors,
+        "long_lines": long_lines,
+        "missing_final_newline": missing_final_newline,
+        "mixed_line_endings": mixed_line_endings,
+    })
+}
+
+#[test]
+fn json_report_contains_expected_keys() {
+    let mut report = Report::default();
+    let clean_stats = |changed_line_count, cleaned_byte_count| CleanStats {
+        changed_line_count,
+        original_byte_count: 100,
+        cleaned_byte_count,
+    };
+    report.record(
+        PathBuf::from("src/main.rs"),
+        FileOutcome::Cleaned(clean_stats(2, 90)),
+    );
+    report.record(
+        PathBuf::from("src/lib.rs"),
+        FileOutcome::WouldBeCleaned(clean_stats(3, 101)),
+    );
+    report.record(PathBuf::from("logo.png"), FileOutcome::Binary);
+    report.record(PathBuf::from("README.md"), FileOutcome::AlreadyClean);
+    report.record(
+        PathBuf::from("secret.txt"),
+        FileOutcome::NotRead(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "permission denied",
+        )),
+    );
+    report.record(
+        PathBuf::from("locked.txt"),
+        FileOutcome::NotWritten(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "permission denied",
+        )),
+    );
+
+    let json: Value = serde_json::from_str(&json_report(&report).to_string()).unwrap();
+
+    assert_eq!(json["counts"]["already_clean"], 1);
+    assert_eq!(json["counts"]["cleaned"], 1);
+    assert_eq!(json["counts"]["would_be_cleaned"], 1);
+    assert_eq!(json["counts"]["skipped"], 3);
+    assert_eq!(json["counts"]["walk_dir_errors"], 0);
+    assert_eq!(json["counts"]["bytes_saved"], 9);
+    assert_eq!(
+        json["cleaned"],
+        json!([{ "path": "src/main.rs", "changed_lines": 2, "bytes_saved": 10 }])
+    );
+    assert_eq!(
+        json["would_be_cleaned"],
+        json!([{ "path": "src/lib.rs", "changed_lines": 3, "bytes_saved": -1 }])
+    );
+    assert_eq!(
+        json["skipped"],
+        json!([
+            { "path": "secret.txt", "reason": "could not be read: permission denied" },
+            { "path": "locked.txt", "reason": "could not be written: permission denied" },
+            { "path": "logo.png", "reason": "binary" },
+        ])
+    );
+    assert_eq!(json["walk_dir_errors"], json!([]));
+    assert_eq!(json["missing_final_newline"], json!([]));
+}
+
+#[test]
+fn stats_count_every_file_without_listing_any() {
+    let mut report = Report::default();
+    report.record(
+        PathBuf::from("src/main.rs"),
+        FileOutcome::WouldBeCleaned(CleanStats {
+            changed_line_count: 3,
+            original_byte_count: 100,
+            cleaned_byte_count: 90,
+        }),
+    );
+    report.record(PathBuf::from("README.md"), FileOutcome::AlreadyClean);
+    report.record(PathBuf::from("logo.png"), FileOutcome::Binary);
+    report.missing_file_paths.push(PathBuf::from("missing.rs"));
+
+    assert_eq!(
+        json_stats(&report),
+        json!({
+            "files": 3,
+            "changed_files": 1,
+            "changed_lines": 3,
+            "already_clean": 1,
+            "skipped": 1,
+            "errors": 1,
+            "bytes_saved": 10,
+        })
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn classify_symlink_loops() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let directory_path = temp_dir.path().join("directory");
+    std::fs::create_dir(&directory_path).unwrap();
+    std::os::unix::fs::symlink(&directory_path, directory_path.join("loop")).unwrap();
+
+    let walk_dir_errors: Vec<Error> = walkdir::WalkDir::new(temp_dir.path())
+        .follow_links(true)
+        .into_iter()
+        .filter_map(Result::err)
+        .collect();
+
+    assert_eq!(walk_dir_errors.len(), 1);
+    assert_eq!(
+        WalkDirErrorKind::of(&walk_dir_errors[0]),
+        WalkDirErrorKind::SymlinkLoop
+    );
+    assert!(describe_walk_dir_error(&walk_dir_errors[0]).starts_with("Symlink loop"));
+}
+
+#[test]
+fn github_annotations_point_at_lines() {
+    let mut report = Report::default();
+    report.issue_files.push((
+        PathBuf::from("src/a,b.rs"),
+        vec![Issue {
+            line_number: 3,
+            rule_id: "trim-trailing-whitespace",
+            message: "Trailing whitespace",
+        }],
+    ));
+    report.record(
+        PathBuf::from("secret.txt"),
+        FileOutcome::NotRead(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "permission denied",
+        )),
+    );
+    report
+        .mixed_line_ending_file_paths
+        .push(PathBuf::from("build.bat"));
+
+    assert_eq!(
+        github_annotations(&report),
+        vec![
+            "::warning file=src/a%2Cb.rs,line=3::Trailing whitespace",
+            "::warning file=build.bat::Mixed line endings",
+            "::error file=secret.txt::permission denied",
+        ]
+    );
+}
+
+#[test]
+fn sarif_report_has_required_fields() {
+    let mut report = Report::default();
+    report.issue_files.push((
+        PathBuf::from("src/main.rs"),
+        vec![
+            Issue {
+                line_number: 2,
+                rule_id: "trim-trailing-whitespace",
+                message: "Trailing whitespace",
+            },
+            Issue {
+                line_number: 5,
+                rule_id: "normalize-final-newlines",
+                message: "Missing final newline or trailing blank line",
+            },
+        ],
+    ));
+
+    let sarif: Value = serde_json::from_str(&sarif_report(&report).to_string()).unwrap();
+
+    assert_eq!(sarif["version"], "2.1.0");
+    assert_eq!(sarif["runs"].as_array().unwrap().len(), 1);
+
+    let run = &sarif["runs"][0];
+    assert_eq!(run["tool"]["driver"]["name"], "lineman");
+
+    let rule_ids: Vec<&str> = run["tool"]["driver"]["rules"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|rule| rule["id"].as_str().unwrap())
+        .collect();
+    assert_eq!(
+        rule_ids,
+        vec!["normalize-final-newlines", "trim-trailing-whitespace"]
+    );
+
+    let results = run["results"].as_array().unwrap();
+    assert_eq!(results.len(), 2);
+
+    for result in results {
+        assert!(result["ruleId"].is_string());
+        assert!(result["message"]["text"].is_string());
+        assert_eq!(
+            result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "src/main.rs"
+        );
+    }
+
+    assert_eq!(
+        results[0]["locations"][0]["physicalLocation"]["region"]["startLine"],
+        2
+    );
+}
+
+#[test]
+fn no_color_disables_auto_color_only() {
+    let no_color = || Some(OsString::from("1"));
+
+    assert!(ColorChoice::Auto.resolve(None, true));
+    assert!(!ColorChoice::Auto.resolve(None, false));
+    assert!(!ColorChoice::Auto.resolve(no_color(), true));
+    assert!(ColorChoice::Auto.resolve(Some(OsString::new()), true));
+    assert!(ColorChoice::Always.resolve(no_color(), false));
+    assert!(!ColorChoice::Never.resolve(None, true));
+}
+
+#[test]
+fn summarize_every_outcome_in_one_line() {
+    let mut report = Report::default();
+    report.record(
+        PathBuf::from("src/main.rs"),
+        FileOutcome::Cleaned(CleanStats {
+            changed_line_count: 3,
+            original_byte_count: 2000,
+            cleaned_byte_count: 771,
+        }),
+    );
+    report.record(PathBuf::from("README.md"), FileOutcome::AlreadyClean);
+    report.record(PathBuf::from("logo.png"), FileOutcome::Binary);
+    report.record(
+        PathBuf::from("secret.txt"),
+        FileOutcome::NotRead(io::Error::from(io::ErrorKind::PermissionDenied)),
+    );
+
+    assert_eq!(
+        report.summary(),
+        "Cleaned 1, already clean 1, skipped 1, errors 1, 1.2KB removed."
+    );
+
+    report.record(
+        PathBuf::from("src/lib.rs"),
+        FileOutcome::WouldBeCleaned(CleanStats {
+            changed_line_count: 1,
+            original_byte_count: 10,
+            cleaned_byte_count: 1300,
+        }),
+    );
+
+    assert_eq!(
+        report.summary(),
+        "Cleaned 1, would be cleaned 1, already clean 1, skipped 1, errors 1, 61B added."
+    );
+    assert_eq!(format_byte_count(5 * 1024 * 1024), "5.0MB");
+    assert_eq!(
+        report.summary_json(),
+        r#"{"cleaned":2,"skipped":1,"errors":1,"bytes_removed":-61}"#
+    );
+    // The JSON report lists every file that wasn't processed as skipped, including those counted as errors here
+    assert_eq!(json_report(&report)["counts"]["skipped"], 2);
+}
+
+#[test]
+fn all_clean_only_when_nothing_else_happened() {
+    let mut report = Report::default();
+    report.record(PathBuf::from("README.md"), FileOutcome::AlreadyClean);
+    assert!(report.is_all_clean());
+
+    report.record(PathBuf::from("logo.png"), FileOutcome::Binary);
+    assert!(!report.is_all_clean());
+}
+
+#[test]
+fn exit_codes_follow_the_worst_outcome() {
+    let mut report = Report::default();
+    report.record(PathBuf::from("README.md"), FileOutcome::AlreadyClean);
+    assert!(!report.has_changes());
+    assert_eq!(report.exit_code(true), 0);
+
+    report.record(
+        PathBuf::from("main.rs"),
+        FileOutcome::WouldBeCleaned(CleanStats {
+            changed_line_count: 1,
+            original_byte_count: 2,
+            cleaned_byte_count: 1,
+        }),
+    );
+    assert!(report.has_changes());
+    assert_eq!(report.exit_code(false), 0);
+    assert_eq!(report.exit_code(true), 1);
+
+    report.missing_file_paths.push(PathBuf::from("missing.rs"));
+    assert_eq!(report.exit_code(true), 5);
+}
+
+#[test]
+fn relativize_paths_to_the_deepest_root() {
+    let mut report = Report::default();
+    report.record(
+        PathBuf::from("/repository/src/main.rs"),
+        FileOutcome::AlreadyClean,
+    );
+    report.record(
+        PathBuf::from("/repository/docs/guide.md"),
+        FileOutcome::Binary,
+    );
+    report.record(PathBuf::from("/elsewhere/notes.txt"), FileOutcome::Binary);
+    report.record(
+        PathBuf::from("/repository/script.sh"),
+        FileOutcome::TooLarge(1024),
+    );
+
+    report.relativize_paths(&[
+        PathBuf::from("/repository"),
+        PathBuf::from("/repository/docs"),
+        PathBuf::from("/repository/script.sh"),
+    ]);
+
+    assert_eq!(
+        report.already_clean_file_paths,
+        vec![PathBuf::from("src/main.rs")]
+    );
+    assert_eq!(
+        report.binary_file_paths,
+        vec![
+            PathBuf::from("guide.md"),
+            PathBuf::from("/elsewhere/notes.txt")
+        ]
+    );
+    assert_eq!(
+        report.oversized_files,
+        vec![(PathBuf::from("script.sh"), 1024)]
+    );
+}
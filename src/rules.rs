@@ -0,0 +1,567 @@
+//! The individual cleaning steps that `clean_lines` runs, in order, over the lines of some content
+
+use crate::LineEnding;
+
+/// A single line, split from its terminator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub content: String,
+    pub line_ending: Option<LineEnding>,
+    /// The 1-based position of the line in the content it was split from, which rules carry along unchanged so that
+    /// issues can be traced back to the original line. `Line::parse` leaves it at 0
+    pub line_number: usize,
+}
+
+impl Line {
+    /// Splits a line as produced by `split_lines` into its content and terminator
+    pub fn parse(line: &str) -> Self {
+        let (content, line_ending) = if let Some(content) = line.strip_suffix("\r\n") {
+            (content, Some(LineEnding::Crlf))
+        } else if let Some(content) = line.strip_suffix('\n') {
+            (content, Some(LineEnding::Lf))
+        } else if let Some(content) = line.strip_suffix('\r') {
+            (content, Some(LineEnding::Cr))
+        } else {
+            (line, None)
+        };
+
+        Line {
+            content: content.to_string(),
+            line_ending,
+            line_number: 0,
+        }
+    }
+
+    /// Parses a line along with its 1-based position in the content it was split from
+    pub fn parse_numbered(line: &str, line_number: usize) -> Self {
+        Line {
+            line_number,
+            ..Line::parse(line)
+        }
+    }
+
+    /// Whether the line has no content besides its terminator. Rules that trim lines run first, so any characters
+    /// left over, such as form feeds kept by a custom trim set, make the line non-blank
+    pub fn is_blank(&self) -> bool {
+        self.content.is_empty()
+    }
+
+    /// Joins the line back together with its terminator
+    pub fn into_string(self) -> String {
+        match self.line_ending {
+            Some(line_ending) => self.content + line_ending.as_str(),
+            None => self.content,
+        }
+    }
+}
+
+/// One step of cleaning, which rewrites, adds or removes lines
+pub trait CleaningRule {
+    fn apply(&self, lines: Vec<Line>) -> Vec<Line>;
+
+    /// A short kebab-case name that identifies the rule in reports
+    fn id(&self) -> &'static str;
+
+    /// Describes the issue this rule fixes, reported for each line it changes or removes
+    fn issue(&self) -> &'static str;
+}
+
+/// Trims trailing characters from each line, which are all Unicode whitespace unless a set of characters is given
+pub struct TrimTrailingWhitespace {
+    pub trim_chars: Option<Vec<char>>,
+}
+
+impl CleaningRule for TrimTrailingWhitespace {
+    fn apply(&self, lines: Vec<Line>) -> Vec<Line> {
+        lines
+            .into_iter()
+            .map(|mut line| {
+                let trimmed_length = trimmed_length(&line.content, self.trim_chars.as_deref());
+
+                line.content.truncate(trimmed_length);
+                line
+            })
+            .collect()
+    }
+
+    fn id(&self) -> &'static str {
+        "trim-trailing-whitespace"
+    }
+
+    fn issue(&self) -> &'static str {
+        "Trailing whitespace"
+    }
+}
+
+/// Removes carriage returns from the content of each line. A `\r` that terminates a line is part of its line ending
+/// rather than its content, so only stray ones are removed
+pub struct StripStrayCarriageReturns;
+
+impl CleaningRule for StripStrayCarriageReturns {
+    fn apply(&self, lines: Vec<Line>) -> Vec<Line> {
+        lines
+            .into_iter()
+            .map(|mut line| {
+                line.content.retain(|character| character != '\r');
+                line
+            })
+            .collect()
+    }
+
+    fn id(&self) -> &'static str {
+        "strip-stray-cr"
+    }
+
+    fn issue(&self) -> &'static str {
+        "Stray carriage return"
+    }
+}
+
+/// The invisible characters removed by `StripInvisibleCharacters` unless a set of characters is given:
+///
+/// - U+200B zero width space
+/// - U+200C zero width non-joiner
+/// - U+200D zero width joiner
+/// - U+2060 word joiner
+/// - U+FEFF zero width no-break space, which is also the byte order mark
+pub const INVISIBLE_CHARACTERS: &[char] =
+    &['\u{200b}', '\u{200c}', '\u{200d}', '\u{2060}', '\u{feff}'];
+
+/// Removes invisible characters from anywhere in the content of each line
+pub struct StripInvisibleCharacters {
+    pub invisible_chars: Vec<char>,
+}
+
+impl CleaningRule for StripInvisibleCharacters {
+    fn apply(&self, lines: Vec<Line>) -> Vec<Line> {
+        lines
+            .into_iter()
+            .map(|mut line| {
+                line.content
+                    .retain(|character| !self.invisible_chars.contains(&character));
+                line
+            })
+            .collect()
+    }
+
+    fn id(&self) -> &'static str {
+        "strip-invisible"
+    }
+
+    fn issue(&self) -> &'static str {
+        "Invisible character"
+    }
+}
+
+/// Trims trailing characters like `TrimTrailingWhitespace`, except that Markdown hard line breaks are kept. A line
+/// with content whose trailing whitespace ends in two or more spaces keeps exactly two spaces, so one trailing space
+/// is removed, three or more are collapsed to two, and blank lines are trimmed completely
+pub struct TrimMarkdownTrailingWhitespace {
+    pub trim_chars: Option<Vec<char>>,
+}
+
+impl CleaningRule for TrimMarkdownTrailingWhitespace {
+    fn apply(&self, lines: Vec<Line>) -> Vec<Line> {
+        lines
+            .into_iter()
+            .map(|mut line| {
+                let trimmed_length = trimmed_length(&line.content, self.trim_chars.as_deref());
+                let is_hard_line_break =
+                    trimmed_length > 0 && line.content[trimmed_length..].ends_with("  ");
+
+                line.content.truncate(trimmed_length);
+
+                if is_hard_line_break {
+                    line.content.push_str("  ");
+                }
+
+                line
+            })
+            .collect()
+    }
+
+    fn id(&self) -> &'static str {
+        "trim-trailing-whitespace"
+    }
+
+    fn issue(&self) -> &'static str {
+        "Trailing whitespace"
+    }
+}
+
+/// The length of a line's content without its trailing characters, which are all Unicode whitespace unless a set of
+/// characters is given
+fn trimmed_length(content: &str, trim_chars: Option<&[char]>) -> usize {
+    match trim_chars {
+        Some(trim_chars) => content
+            .trim_end_matches(|character| trim_chars.contains(&character))
+            .len(),
+        None => content.trim_end().len(),
+    }
+}
+
+/// Removes trailing blank lines beyond the given number and ensures the final line ends with the given line ending.
+/// Blank lines are only kept after content, so blank content is still emptied
+pub struct NormalizeFinalNewlines {
+    pub line_ending: LineEnding,
+    pub trailing_blank_lines: usize,
+    /// Whether a final line without a line ending gets one, as opposed to only removing trailing blank lines
+    pub add_final_newline: bool,
+}
+
+impl CleaningRule for NormalizeFinalNewlines {
+    fn apply(&self, mut lines: Vec<Line>) -> Vec<Line> {
+        let kept_line_count = match lines.iter().rposition(|line| !line.is_blank()) {
+            Some(last_content_index) => lines
+                .len()
+                .min(last_content_index + 1 + self.trailing_blank_lines),
+            None => 0,
+        };
+
+        lines.truncate(kept_line_count);
+
+        if let Some(last_line) = lines.last_mut() {
+            if self.add_final_newline || last_line.line_ending.is_some() {
+                last_line.line_ending = Some(self.line_ending);
+            }
+        }
+
+        lines
+    }
+
+    fn id(&self) -> &'static str {
+        "normalize-final-newlines"
+    }
+
+    fn issue(&self) -> &'static str {
+        "Missing final newline or trailing blank line"
+    }
+}
+
+/// Rewrites the terminator of every line that has one to the given line ending
+pub struct RewriteLineEndings {
+    pub line_ending: LineEnding,
+}
+
+impl CleaningRule for RewriteLineEndings {
+    fn apply(&self, lines: Vec<Line>) -> Vec<Line> {
+        lines
+            .into_iter()
+            .map(|mut line| {
+                if line.line_ending.is_some() {
+                    line.line_ending = Some(self.line_ending);
+                }
+
+                line
+            })
+            .collect()
+    }
+
+    fn id(&self) -> &'static str {
+        "rewrite-line-endings"
+    }
+
+    fn issue(&self) -> &'static str {
+        "Inconsistent line ending"
+    }
+}
+
+/// Replaces the tabs in each line's leading indentation with spaces up to the next tab stop
+pub struct ExpandLeadingTabs {
+    pub tab_width: usize,
+}
+
+impl CleaningRule for ExpandLeadingTabs {
+    fn apply(&self, lines: Vec<Line>) -> Vec<Line> {
+        lines
+            .into_iter()
+            .map(|mut line| {
+                line.content = expand_leading_tabs(&line.content, self.tab_width);
+                line
+            })
+            .collect()
+    }
+
+    fn id(&self) -> &'static str {
+        "expand-leading-tabs"
+    }
+
+    fn issue(&self) -> &'static str {
+        "Indented with tabs"
+    }
+}
+
+/// Rewrites each line's leading indentation as tabs, keeping any leftover columns narrower than a full tab as spaces
+pub struct CollapseLeadingSpaces {
+    pub tab_width: usize,
+}
+
+impl CleaningRule for CollapseLeadingSpaces {
+    fn apply(&self, lines: Vec<Line>) -> Vec<Line> {
+        lines
+            .into_iter()
+            .map(|mut line| {
+                line.content = collapse_leading_spaces(&line.content, self.tab_width);
+                line
+            })
+            .collect()
+    }
+
+    fn id(&self) -> &'static str {
+        "collapse-leading-spaces"
+    }
+
+    fn issue(&self) -> &'static str {
+        "Indented with spaces"
+    }
+}
+
+/// Removes blank lines from the start of the content. Only blank lines are removed, so a shebang or any other first
+/// content is always kept
+pub struct TrimLeadingBlankLines;
+
+impl CleaningRule for TrimLeadingBlankLines {
+    fn apply(&self, lines: Vec<Line>) -> Vec<Line> {
+        lines.into_iter().skip_while(Line::is_blank).collect()
+    }
+
+    fn id(&self) -> &'static str {
+        "trim-leading-blank-lines"
+    }
+
+    fn issue(&self) -> &'static str {
+        "Leading blank line"
+    }
+}
+
+/// Drops blank lines from any run of consecutive blank lines longer than the given maximum
+pub struct CollapseBlankLines {
+    pub max_blank_line_count: usize,
+}
+
+impl CleaningRule for CollapseBlankLines {
+    fn apply(&self, lines: Vec<Line>) -> Vec<Line> {
+        let mut blank_line_count = 0;
+
+        lines
+            .into_iter()
+            .filter(|line| {
+                if line.is_blank() {
+                    blank_line_count += 1;
+                    blank_line_count <= self.max_blank_line_count
+                } else {
+                    blank_line_count = 0;
+                    true
+                }
+            })
+            .collect()
+    }
+
+    fn id(&self) -> &'static str {
+        "collapse-blank-lines"
+    }
+
+    fn issue(&self) -> &'static str {
+        "Too many consecutive blank lines"
+    }
+}
+
+/// Drops every trailing blank line and terminates the last remaining line, so that the lines end in exactly one line
+/// ending. Lines that are all blank end up empty, since there is no content to terminate
+pub struct SingleFinalNewline {
+    pub line_ending: LineEnding,
+}
+
+impl CleaningRule for SingleFinalNewline {
+    fn apply(&self, mut lines: Vec<Line>) -> Vec<Line> {
+        while lines.last().is_some_and(Line::is_blank) {
+            lines.pop();
+        }
+
+        if let Some(last_line) = lines.last_mut() {
+            last_line.line_ending.get_or_insert(self.line_ending);
+        }
+
+        lines
+    }
+
+    fn id(&self) -> &'static str {
+        "single-final-newline"
+    }
+
+    fn issue(&self) -> &'static str {
+        "Missing final newline or trailing blank line"
+    }
+}
+
+/// Replaces the tabs in a line's leading indentation with spaces up to the next tab stop, leaving any tabs after the
+/// first non-whitespace character alone
+fn expand_leading_tabs(line: &str, tab_width: usize) -> String {
+    let indentation_length = line.len() - line.trim_start_matches([' ', '\t']).len();
+    let (indentation, content) = line.split_at(indentation_length);
+    let mut expanded_indentation = String::new();
+
+    for character in indentation.chars() {
+        if character == '\t' {
+            let space_count = tab_width - expanded_indentation.len() % tab_width;
+            expanded_indentation.push_str(&" ".repeat(space_count));
+        } else {
+            expanded_indentation.push(character);
+        }
+    }
+
+    expanded_indentation + content
+}
+
+fn collapse_leading_spaces(line: &str, tab_width: usize) -> String {
+    let expanded_line = expand_leading_tabs(line, tab_width);
+    let content = expanded_line.trim_start_matches(' ');
+    let indentation_width = expanded_line.len() - content.len();
+
+    "\t".repeat(indentation_width / tab_width)
+        + &" ".repeat(indentation_width % tab_width)
+        + content
+}
+
+#[cfg(test)]
+fn parse_lines(lines: &[&str]) -> Vec<Line> {
+    lines.iter().map(|line| Line::parse(line)).collect()
+}
+
+#[cfg(test)]
+fn join_lines(lines: Vec<Line>) -> Vec<String> {
+    lines.into_iter().map(Line::into_string).collect()
+}
+
+#[test]
+fn split_and_join_lines() {
+    for line in ["content\n", "content\r\n", "content\r", "content", ""] {
+        assert_eq!(Line::parse(line).into_string(), line);
+    }
+
+    assert_eq!(
+        Line::parse("  content \r\n"),
+        Line {
+            content: "  content ".to_string(),
+            line_ending: Some(LineEnding::Crlf),
+            line_number: 0,
+        }
+    );
+}
+
+#[test]
+fn trim_trailing_whitespace_rule() {
+    let lines = parse_lines(&["first \t\n", "second\n", " \n", "last  "]);
+    let rule = TrimTrailingWhitespace { trim_chars: None };
+
+    assert_eq!(
+        join_lines(rule.apply(lines)),
+        vec!["first\n", "second\n", "\n", "last"]
+    );
+}
+
+#[test]
+fn expand_only_leading_tabs_rule() {
+    let rule = ExpandLeadingTabs { tab_width: 4 };
+
+    assert_eq!(
+        join_lines(rule.apply(parse_lines(&[
+            "\t\tfoo\tbar\n",
+            " \tbaz\t\n",
+            "qux\t\tquux"
+        ]))),
+        vec!["        foo\tbar\n", "    baz\t\n", "qux\t\tquux"]
+    );
+}
+
+#[test]
+fn normalize_final_newlines_rule() {
+    let rule = NormalizeFinalNewlines {
+        line_ending: LineEnding::Lf,
+        trailing_blank_lines: 0,
+        add_final_newline: true,
+    };
+
+    assert_eq!(
+        join_lines(rule.apply(parse_lines(&["first\n", "last"]))),
+        vec!["first\n", "last\n"]
+    );
+    assert_eq!(
+        join_lines(rule.apply(parse_lines(&["first\n", "last\n", "\n", "\n"]))),
+        vec!["first\n", "last\n"]
+    );
+
+    let rule = NormalizeFinalNewlines {
+        line_ending: LineEnding::Lf,
+        trailing_blank_lines: 1,
+        add_final_newline: true,
+    };
+
+    assert_eq!(
+        join_lines(rule.apply(parse_lines(&["last\n", "\n", "\n"]))),
+        vec!["last\n", "\n"]
+    );
+    assert_eq!(
+        join_lines(rule.apply(parse_lines(&["last\n"]))),
+        vec!["last\n"]
+    );
+    assert!(rule.apply(parse_lines(&["\n", "\n"])).is_empty());
+
+    let rule = NormalizeFinalNewlines {
+        line_ending: LineEnding::Lf,
+        trailing_blank_lines: 0,
+        add_final_newline: false,
+    };
+
+    assert_eq!(
+        join_lines(rule.apply(parse_lines(&["first\n", "last"]))),
+        vec!["first\n", "last"]
+    );
+    assert_eq!(
+        join_lines(rule.apply(parse_lines(&["last\n", "\n", "\n"]))),
+        vec!["last\n"]
+    );
+}
+
+#[test]
+fn strip_invisible_characters() {
+    let rule = StripInvisibleCharacters {
+        invisible_chars: INVISIBLE_CHARACTERS.to_vec(),
+    };
+
+    for invisible_char in INVISIBLE_CHARACTERS {
+        let lines = rule.apply(vec![Line::parse(&format!("a{}b\n", invisible_char))]);
+        assert_eq!(lines[0].content, "ab");
+    }
+
+    // Only the given characters are removed, so the soft hyphen stays
+    let lines = rule.apply(vec![Line::parse(
+        "\u{feff}zero\u{200b}width\u{00ad}\u{2060}\n",
+    )]);
+    assert_eq!(lines[0].content, "zerowidth\u{00ad}");
+    assert_eq!(lines[0].line_ending, Some(LineEnding::Lf));
+}
+
+#[test]
+fn keep_markdown_hard_line_breaks() {
+    let rule = TrimMarkdownTrailingWhitespace { trim_chars: None };
+    let lines = rule.apply(
+        [
+            "one \n",
+            "two  \n",
+            "three   \n",
+            "four    \n",
+            "tab\t  \n",
+            "    \n",
+        ]
+        .into_iter()
+        .map(Line::parse)
+        .collect(),
+    );
+    let contents: Vec<&str> = lines.iter().map(|line| line.content.as_str()).collect();
+
+    assert_eq!(
+        contents,
+        vec!["one", "two  ", "three  ", "four  ", "tab  ", ""]
+    );
+}
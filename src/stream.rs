@@ -0,0 +1,265 @@
+//! Cleaning that reads and writes content one line at a time, so that large inputs never have to be held in memory
+
+use crate::rules::Line;
+use crate::{
+    dominant_line_ending, is_mixed, restore_first_line, strip_bom, CleanOptions, LineEnding,
+};
+use std::io::{self, BufRead, Write};
+use std::str;
+
+/// What streaming changed, counted as the content passes through
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StreamStats {
+    pub changed_line_count: usize,
+    pub input_byte_count: usize,
+    pub output_byte_count: usize,
+    /// Whether the content was missing the newline at the end of its last line, and cleaning added it
+    pub added_final_newline: bool,
+    /// Whether the content ended its lines in more than one style
+    pub has_mixed_line_endings: bool,
+}
+
+/// The line endings of content that is read one line at a time, counted as each line is read
+#[derive(Default)]
+struct LineEndingCounts {
+    lf_count: usize,
+    crlf_count: usize,
+    cr_count: usize,
+}
+
+impl LineEndingCounts {
+    /// Counts the line endings of everything up to and including a line's `\n`
+    fn add(&mut self, line: &[u8]) {
+        let is_crlf = line.ends_with(b"\r\n");
+
+        if is_crlf {
+            self.crlf_count += 1;
+        } else if line.ends_with(b"\n") {
+            self.lf_count += 1;
+        }
+
+        self.cr_count += line.iter().filter(|byte| **byte == b'\r').count() - usize::from(is_crlf);
+    }
+}
+
+/// Whether content can be cleaned with these options by `clean_stream`. Only rules that look at a single line, and
+/// trimming blank lines from the end, can be streamed. CR-terminated content is split on a lone `\r`, which reading
+/// up to each `\n` can't do
+pub fn is_streamable(options: &CleanOptions) -> bool {
+    !options.trim_leading_blank_lines
+        && options.max_consecutive_blank_lines.is_none()
+        && !options.single_final_newline
+        && options.line_ending != Some(LineEnding::Cr)
+}
+
+/// Finds the most common line ending by reading through content one line at a time, with the same tie-breaking as
+/// `detect_line_ending`
+pub fn detect_stream_line_ending<R: BufRead>(mut reader: R) -> io::Result<Option<LineEnding>> {
+    let mut line_ending_counts = LineEndingCounts::default();
+    let mut line = Vec::new();
+
+    while reader.read_until(b'\n', &mut line)? > 0 {
+        line_ending_counts.add(&line);
+        line.clear();
+    }
+
+    Ok(dominant_line_ending(
+        line_ending_counts.lf_count,
+        line_ending_counts.crlf_count,
+        line_ending_counts.cr_count,
+    ))
+}
+
+/// Cleans UTF-8 content line by line from a reader into a writer, producing the same output as `clean_string` for
+/// options that `is_streamable` accepts. Blank lines are held back until a line with content follows them, since a
+/// run of blank lines at the very end is dropped when normalizing EOF newlines
+pub fn clean_stream<R: BufRead, W: Write>(
+    mut reader: R,
+    writer: &mut W,
+    options: &CleanOptions,
+    line_ending: LineEnding,
+) -> io::Result<StreamStats> {
+    let line_rules = options.line_rules(line_ending);
+    let mut stats = StreamStats::default();
+    // Each held back blank line is kept along with whether cleaning changed it
+    let mut blank_lines: Vec<(String, bool)> = Vec::new();
+    let mut line_bytes = Vec::new();
+    let mut is_first_line = true;
+    let mut has_content = false;
+    let mut last_line_is_unterminated = false;
+    let mut line_ending_counts = LineEndingCounts::default();
+
+    while reader.read_until(b'\n', &mut line_bytes)? > 0 {
+        stats.input_byte_count += line_bytes.len();
+        line_ending_counts.add(&line_bytes);
+        last_line_is_unterminated = !line_bytes.ends_with(b"\n");
+
+        let input_line = str::from_utf8(&line_bytes)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))?;
+        let content = if is_first_line && options.strip_bom {
+            strip_bom(input_line)
+        } else {
+            input_line
+        };
+        let line = Line::parse_numbered(content, if is_first_line { 1 } else { 0 });
+        let first_line =
+            (is_first_line && options.preserve_first_line).then(|| line.content.clone());
+        is_first_line = false;
+
+        let cleaned_lines = line_rules.iter().fold(vec![line], |lines, rule| {
+            restore_first_line(rule.apply(lines), first_line.as_deref())
+        });
+
+        for mut cleaned_line in cleaned_lines {
+            // Only the last line can be missing its terminator
+            if options.normalize_eof_newlines
+                && !options.normalize_trailing_blanks_only
+                && cleaned_line.line_ending.is_none()
+            {
+                cleaned_line.line_ending = Some(line_ending);
+            }
+
+            let is_blank = cleaned_line.is_blank();
+            let cleaned_line = cleaned_line.into_string();
+            let is_changed = cleaned_line != input_line;
+
+            if options.normalize_eof_newlines && is_blank {
+                blank_lines.push((cleaned_line, is_changed));
+                continue;
+            }
+
+            for (blank_line, blank_line_is_changed) in blank_lines.drain(..) {
+                write_line(writer, &blank_line, blank_line_is_changed, &mut stats)?;
+            }
+
+            write_line(writer, &cleaned_line, is_changed, &mut stats)?;
+            has_content = true;
+        }
+
+        line_bytes.clear();
+    }
+
+    // Whatever blank lines are still held back trail the content, so all but the ones that are kept are dropped
+    let kept_blank_line_count = if has_content {
+        blank_lines.len().min(options.trailing_blank_lines)
+    } else {
+        0
+    };
+
+    for (blank_line, blank_line_is_changed) in blank_lines.drain(..kept_blank_line_count) {
+        write_line(writer, &blank_line, blank_line_is_changed, &mut stats)?;
+    }
+
+    stats.changed_line_count += blank_lines.len();
+    stats.added_final_newline &= last_line_is_unterminated;
+    stats.has_mixed_line_endings = is_mixed(
+        line_ending_counts.lf_count,
+        line_ending_counts.crlf_count,
+        line_ending_counts.cr_count,
+    );
+
+    Ok(stats)
+}
+
+fn write_line<W: Write>(
+    writer: &mut W,
+    line: &str,
+    is_changed: bool,
+    stats: &mut StreamStats,
+) -> io::Result<()> {
+    writer.write_all(line.as_bytes())?;
+    stats.output_byte_count += line.len();
+    stats.changed_line_count += usize::from(is_changed);
+    // Whether the output ends in a newline so far, which only counts as added once the input turns out not to
+    if !line.is_empty() {
+        stats.added_final_newline = line.ends_with('\n');
+    }
+
+    Ok(())
+}
+
+#[test]
+fn clean_stream_matches_clean_string() {
+    let inputs = [
+        "first  \nsecond\t\n\n  \n",
+        "first\r\nsecond \r\n\r\nlast",
+        "\u{feff}content \n\n\nmore\n \n",
+        "   \n\t\n",
+        "no terminator  ",
+        "content\n  ",
+        "mixed\r\nline\nendings\r\n",
+        "",
+    ];
+    let option_sets = [
+        CleanOptions::default(),
+        CleanOptions {
+            normalize_eof_newlines: false,
+            ..CleanOptions::default()
+        },
+        CleanOptions {
+            strip_bom: true,
+            tabs_to_spaces: true,
+            line_ending: Some(LineEnding::Crlf),
+            ..CleanOptions::default()
+        },
+        CleanOptions {
+            preserve_first_line: true,
+            trailing_blank_lines: 1,
+            ..CleanOptions::default()
+        },
+        CleanOptions {
+            normalize_trailing_blanks_only: true,
+            ..CleanOptions::default()
+        },
+    ];
+
+    for options in &option_sets {
+        assert!(is_streamable(options));
+
+        for input in inputs {
+            let line_ending = options
+                .line_ending
+                .or(detect_stream_line_ending(input.as_bytes()).unwrap())
+                .unwrap_or(LineEnding::Lf);
+            let mut output = Vec::new();
+            let stats = clean_stream(input.as_bytes(), &mut output, options, line_ending).unwrap();
+            let expected_output = crate::clean_string(input, options);
+            let expected_lines: Vec<String> = crate::split_lines(&expected_output)
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+
+            assert_eq!(String::from_utf8(output).unwrap(), expected_output);
+            assert_eq!(stats.input_byte_count, input.len());
+            assert_eq!(stats.output_byte_count, expected_output.len());
+            assert_eq!(
+                stats.changed_line_count,
+                crate::count_changed_lines(&crate::split_lines(input), &expected_lines)
+            );
+            assert_eq!(
+                stats.added_final_newline,
+                crate::adds_final_newline(&crate::split_lines(input), &expected_lines)
+            );
+            assert_eq!(
+                stats.has_mixed_line_endings,
+                crate::has_mixed_line_endings(input.as_bytes())
+            );
+        }
+    }
+}
+
+#[test]
+fn detect_stream_line_endings() {
+    assert_eq!(
+        detect_stream_line_ending("a\r\nb\r\nc\n".as_bytes()).unwrap(),
+        Some(LineEnding::Crlf)
+    );
+    assert_eq!(
+        detect_stream_line_ending("a\rb\rc\n".as_bytes()).unwrap(),
+        Some(LineEnding::Cr)
+    );
+    assert_eq!(
+        detect_stream_line_ending("no line endings".as_bytes()).unwrap(),
+        None
+    );
+}
@@ -0,0 +1,297 @@
+use crate::ignore_files::IgnoreFiles;
+use crate::report::{FileOutcome, Report};
+use crate::{
+    ignore_file_names, is_temp_file, process_file, record_processed_file, Filter,
+    LinemanApplicationError, LinemanArgs, ProcessOptions, BACKUP_EXTENSION,
+};
+use indicatif::ProgressBar;
+use notify::{Event, EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeSet, HashMap};
+use std::ffi::OsStr;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, SystemTime};
+
+/// How long the root paths have to go without changes before the files that changed are cleaned. Editors often save
+/// a file as several writes and renames, and this keeps a single save from cleaning the file more than once
+const DEBOUNCE_DURATION: Duration = Duration::from_millis(200);
+
+/// How often Ctrl-C is checked for while waiting for changes
+const INTERRUPT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Watches the root paths after the initial run, cleaning each selected file again whenever it changes. Returns once
+/// Ctrl-C is pressed, after finishing any files that are being cleaned
+pub fn watch(
+    args: &LinemanArgs,
+    process_options: &ProcessOptions,
+    filter: &Filter,
+) -> Result<(), LinemanApplicationError> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let handler_interrupted = Arc::clone(&interrupted);
+
+    ctrlc::set_handler(move || handler_interrupted.store(true, Ordering::SeqCst))
+        .map_err(|error| LinemanApplicationError::WatchFailed(error.to_string()))?;
+
+    let (event_sender, event_receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(event_sender)
+        .map_err(|error| LinemanApplicationError::WatchFailed(error.to_string()))?;
+
+    for root_path in &args.paths {
+        watcher
+            .watch(root_path, RecursiveMode::Recursive)
+            .map_err(|error| {
+                LinemanApplicationError::WatchFailed(format!(
+                    "{} could not be watched: {}",
+                    root_path.display(),
+                    error
+                ))
+            })?;
+    }
+
+    let mut file_selector = ChangedFileSelector::new(&args.paths, filter);
+    let mut written_files = WrittenFiles::default();
+
+    if !args.quiet {
+        eprintln!("Watching for changes, press Ctrl-C to stop");
+    }
+
+    while !interrupted.load(Ordering::SeqCst) {
+        let mut changed_paths = BTreeSet::new();
+
+        match event_receiver.recv_timeout(INTERRUPT_POLL_INTERVAL) {
+            Ok(event_result) => add_changed_paths(&mut changed_paths, event_result),
+            Err(mpsc::RecvTimeoutError::Timeout) => continue,
+            Err(mpsc::RecvTimeoutError::Disconnected) => break,
+        }
+
+        while let Ok(event_result) = event_receiver.recv_timeout(DEBOUNCE_DURATION) {
+            add_changed_paths(&mut changed_paths, event_result);
+        }
+
+        let file_paths: Vec<PathBuf> = changed_paths
+            .into_iter()
+            .filter_map(|changed_path| file_selector.select(&changed_path))
+            .filter(|file_path| !written_files.is_unchanged_since_written(file_path))
+            .collect();
+
+        if file_paths.is_empty() {
+            continue;
+        }
+
+        let mut report = Report::default();
+
+        for file_path in file_paths {
+            let processed_file = process_file(&file_path, process_options, &ProgressBar::hidden());
+
+            if let FileOutcome::Cleaned(_) = processed_file.file_outcome {
+                written_files.record(&file_path);
+            }
+
+            record_processed_file(&mut report, file_path, processed_file);
+        }
+
+        if !args.quiet {
+            if args.relative_paths {
+                report.relativize_paths(&args.paths);
+            }
+
+            report.print(args.format, args.color.use_color());
+        }
+    }
+
+    Ok(())
+}
+
+/// Collects the paths of an event that may leave a file with new content. Removed files have nothing left to clean
+fn add_changed_paths(changed_paths: &mut BTreeSet<PathBuf>, event_result: notify::Result<Event>) {
+    match event_result {
+        Ok(event) => {
+            if !matches!(event.kind, EventKind::Access(_) | EventKind::Remove(_)) {
+                changed_paths.extend(event.paths);
+            }
+        }
+        Err(error) => eprintln!("Watch error: {}", error),
+    }
+}
+
+/// Decides which changed paths are cleaned, applying the same selection as the initial walk
+struct ChangedFileSelector<'a> {
+    filter: &'a Filter,
+    /// Each root path alongside its canonical form, which is what changed paths are compared against
+    root_paths: Vec<(PathBuf, PathBuf)>,
+    ignore_files: HashMap<PathBuf, IgnoreFiles>,
+}
+
+impl<'a> ChangedFileSelector<'a> {
+    fn new(root_paths: &[PathBuf], filter: &'a Filter) -> Self {
+        let root_paths = root_paths
+            .iter()
+            .filter_map(|root_path| {
+                fs::canonicalize(root_path)
+                    .ok()
+                    .map(|canonical_root_path| (root_path.clone(), canonical_root_path))
+            })
+            .collect();
+
+        ChangedFileSelector {
+            filter,
+            root_paths,
+            ignore_files: HashMap::new(),
+        }
+    }
+
+    /// Returns the path to clean a changed file at, relative to the root path it was found under, if it's selected
+    fn select(&mut self, changed_path: &Path) -> Option<PathBuf> {
+        if !changed_path.is_file() || is_temp_file(changed_path) {
+            return None;
+        }
+
+        if self.filter.skip_backups
+            && changed_path.extension() == Some(OsStr::new(BACKUP_EXTENSION))
+        {
+            return None;
+        }
+
+        let canonical_path = fs::canonicalize(changed_path).ok()?;
+
+        for (root_path, canonical_root_path) in &self.root_paths {
+            // A file named directly is processed as-is, without applying the file selection
+            if canonical_path == *canonical_root_path {
+                return Some(root_path.clone());
+            }
+
+            let Ok(relative_path) = canonical_path.strip_prefix(canonical_root_path) else {
+                continue;
+            };
+
+            if self
+                .filter
+                .max_depth
+                .is_some_and(|max_depth| relative_path.components().count() > max_depth)
+            {
+                return None;
+            }
+
+            let ignore_files = self
+                .ignore_files
+                .entry(canonical_root_path.clone())
+                .or_insert_with(|| {
+                    IgnoreFiles::new(
+                        canonical_root_path,
+                        ignore_file_names(self.filter.no_ignore),
+                    )
+                });
+
+            // The walk never enters ignored directories, so a file inside one is ignored as well
+            let is_ignored = canonical_path
+                .ancestors()
+                .take_while(|ancestor| ancestor != canonical_root_path)
+                .enumerate()
+                .any(|(depth, ancestor)| {
+                    let is_dir = depth > 0;
+                    let is_ignored_dir = is_dir
+                        && ancestor
+                            .file_name()
+                            .is_some_and(|dir_name| self.filter.is_ignored_dir(dir_name));
+
+                    is_ignored_dir || ignore_files.is_ignored(ancestor, is_dir)
+                });
+
+            let path = root_path.join(relative_path);
+            let is_selected = !is_ignored && self.filter.matches(&path, relative_path);
+
+            return is_selected.then_some(path);
+        }
+
+        None
+    }
+}
+
+/// The files lineman has written while watching, so that the events caused by its own writes don't get them cleaned
+/// again. A file counts as unchanged for as long as its modification time and length are the ones it was written with
+#[derive(Default)]
+struct WrittenFiles {
+    fingerprints: HashMap<PathBuf, (SystemTime, u64)>,
+}
+
+impl WrittenFiles {
+    fn record(&mut self, path: &Path) {
+        if let Some(fingerprint) = file_fingerprint(path) {
+            self.fingerprints.insert(path.to_path_buf(), fingerprint);
+        }
+    }
+
+    fn is_unchanged_since_written(&self, path: &Path) -> bool {
+        self.fingerprints
+            .get(path)
+            .is_some_and(|fingerprint| file_fingerprint(path).as_ref() == Some(fingerprint))
+    }
+}
+
+fn file_fingerprint(path: &Path) -> Option<(SystemTime, u64)> {
+    let metadata = fs::metadata(path).ok()?;
+
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+#[test]
+fn select_changed_files_like_the_initial_walk() {
+    use structopt::StructOpt;
+
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    fs::create_dir_all(root_path.join("src")).unwrap();
+    fs::create_dir_all(root_path.join("target")).unwrap();
+    fs::write(root_path.join(".gitignore"), "target/\n").unwrap();
+
+    for file_name in ["src/main.rs", "src/notes.txt", "target/build.rs"] {
+        fs::write(root_path.join(file_name), "content\n").unwrap();
+    }
+
+    let temp_path = crate::temp_file_path(&root_path.join("src/main.rs"));
+    fs::write(&temp_path, "content\n").unwrap();
+
+    let args = LinemanArgs::from_iter([
+        OsStr::new("lineman"),
+        OsStr::new("-p"),
+        root_path.as_os_str(),
+        OsStr::new("-e"),
+        OsStr::new("rs"),
+        OsStr::new("--hidden"),
+    ]);
+    let filter = Filter::new(&args).unwrap();
+    let mut file_selector = ChangedFileSelector::new(&args.paths, &filter);
+
+    assert_eq!(
+        file_selector.select(&root_path.join("src/main.rs")),
+        Some(root_path.join("src/main.rs"))
+    );
+    assert_eq!(file_selector.select(&root_path.join("src/notes.txt")), None);
+    assert_eq!(
+        file_selector.select(&root_path.join("target/build.rs")),
+        None
+    );
+    assert_eq!(file_selector.select(&temp_path), None);
+    assert_eq!(
+        file_selector.select(&root_path.join("src/removed.rs")),
+        None
+    );
+}
+
+#[test]
+fn skip_files_unchanged_since_lineman_wrote_them() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("main.rs");
+    fs::write(&path, "content\n").unwrap();
+
+    let mut written_files = WrittenFiles::default();
+    assert!(!written_files.is_unchanged_since_written(&path));
+
+    written_files.record(&path);
+    assert!(written_files.is_unchanged_since_written(&path));
+
+    fs::write(&path, "new content\n").unwrap();
+    assert!(!written_files.is_unchanged_since_written(&path));
+}
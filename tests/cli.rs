@@ -0,0 +1,386 @@
+//! End-to-end tests that run the lineman binary over temporary directory trees, covering the walk, the file
+//! selection and the writes together
+
+use serde_json::Value;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+/// Runs lineman from inside the given directory, so that no `lineman.toml` from elsewhere is picked up
+fn run_lineman(working_dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lineman"))
+        .current_dir(working_dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+/// Runs lineman with the given content piped into its stdin
+fn run_lineman_with_stdin(working_dir: &Path, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_lineman"))
+        .current_dir(working_dir)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+
+    child.wait_with_output().unwrap()
+}
+
+/// Creates a small repository with dirty and clean files of several kinds, an ignored build directory and a binary
+/// file
+fn create_tree(root_path: &Path) {
+    let files: [(&str, &[u8]); 7] = [
+        (".gitignore", b"target/\n"),
+        (
+            "src/main.rs",
+            b"fn main() {   \n    println!(\"hi\");\t\n}\n\n\n",
+        ),
+        ("src/lib.rs", b"pub fn lib() {}\n"),
+        ("scripts/build.py", b"print(\"build\")  \r\n"),
+        ("notes.txt", b"notes   \n"),
+        ("target/generated.rs", b"fn generated() {}   \n"),
+        ("assets/logo.rs", b"\x00\x01binary   \n"),
+    ];
+
+    for (relative_path, content) in files {
+        let path = root_path.join(relative_path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+}
+
+fn read(root_path: &Path, relative_path: &str) -> String {
+    fs::read_to_string(root_path.join(relative_path)).unwrap()
+}
+
+#[test]
+fn clean_a_tree_and_report_each_file() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    create_tree(root_path);
+
+    let output = run_lineman(
+        root_path,
+        &[
+            "-p",
+            ".",
+            "-e",
+            "rs",
+            "py",
+            "--format",
+            "json",
+            "--relative-paths",
+        ],
+    );
+    assert!(output.status.success());
+
+    assert_eq!(
+        read(root_path, "src/main.rs"),
+        "fn main() {\n    println!(\"hi\");\n}\n"
+    );
+    assert_eq!(read(root_path, "scripts/build.py"), "print(\"build\")\r\n");
+    assert_eq!(read(root_path, "src/lib.rs"), "pub fn lib() {}\n");
+    assert_eq!(read(root_path, "notes.txt"), "notes   \n");
+    assert_eq!(
+        read(root_path, "target/generated.rs"),
+        "fn generated() {}   \n"
+    );
+
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    let mut cleaned_paths: Vec<&str> = report["cleaned"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|cleaned_file| cleaned_file["path"].as_str().unwrap())
+        .collect();
+    cleaned_paths.sort_unstable();
+
+    assert_eq!(cleaned_paths, vec!["scripts/build.py", "src/main.rs"]);
+    assert_eq!(report["counts"]["already_clean"], 1);
+    assert_eq!(
+        report["skipped"],
+        serde_json::json!([{ "path": "assets/logo.rs", "reason": "binary" }])
+    );
+}
+
+#[test]
+fn check_reports_dirty_files_without_writing_them() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    create_tree(root_path);
+
+    let output = run_lineman(root_path, &["-p", ".", "-e", "rs", "--check"]);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stdout.contains("Files That Would Be Cleaned:"));
+    assert!(stdout.contains("main.rs"));
+    // The bytes saved over the whole run are only given once, in the summary line
+    assert!(!stdout.contains("Total:"));
+    assert!(stdout.contains("removed."));
+    assert_eq!(
+        read(root_path, "src/main.rs"),
+        "fn main() {   \n    println!(\"hi\");\t\n}\n\n\n"
+    );
+}
+
+#[test]
+fn a_clean_tree_prints_a_single_line() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    create_tree(root_path);
+
+    assert!(run_lineman(root_path, &["-p", ".", "-e", "rs", "py"])
+        .status
+        .success());
+
+    let output = run_lineman(root_path, &["-p", ".", "-e", "rs", "py", "--check"]);
+
+    // The binary file is still skipped, so it is the only thing listed besides the summary
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("Skipped Binary Files:"));
+
+    let output = run_lineman(root_path, &["-p", "src", "--check", "-e", "rs"]);
+
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "All files clean\n"
+    );
+}
+
+#[test]
+fn files_under_a_nested_root_are_cleaned_once_and_counted_as_duplicates() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    create_tree(root_path);
+
+    let output = run_lineman(
+        root_path,
+        &["-p", ".", "src", "-e", "rs", "--format", "json"],
+    );
+    assert!(output.status.success());
+
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(report["counts"]["cleaned"], 1);
+    assert_eq!(report["counts"]["already_clean"], 1);
+    assert_eq!(report["counts"]["duplicates"], 2);
+    assert_eq!(
+        read(root_path, "src/main.rs"),
+        "fn main() {\n    println!(\"hi\");\n}\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn files_reached_through_two_roots_are_cleaned_once() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    fs::create_dir_all(root_path.join("app")).unwrap();
+    fs::create_dir_all(root_path.join("shared")).unwrap();
+    fs::write(root_path.join("app/main.rs"), "fn main() {}   \n").unwrap();
+    fs::write(root_path.join("shared/lib.rs"), "pub fn lib() {}   \n").unwrap();
+    std::os::unix::fs::symlink(root_path.join("shared"), root_path.join("app/shared")).unwrap();
+
+    let output = run_lineman(
+        root_path,
+        &[
+            "-p",
+            "app",
+            "shared",
+            "-e",
+            "rs",
+            "--follow-symlinks",
+            "--format",
+            "json",
+        ],
+    );
+    assert!(output.status.success());
+
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(report["counts"]["cleaned"], 2);
+    assert_eq!(report["counts"]["duplicates"], 1);
+    assert_eq!(read(root_path, "shared/lib.rs"), "pub fn lib() {}\n");
+}
+
+#[test]
+fn stdin_is_cleaned_with_the_rules_of_its_file_name() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let content = "first  \nsecond   \n";
+    let args = ["--stdin", "--markdown"];
+
+    let output = run_lineman_with_stdin(temp_dir.path(), &args, content);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "first\nsecond\n");
+
+    let output = run_lineman_with_stdin(
+        temp_dir.path(),
+        &[&args[..], &["--stdin-filename", "README.md"]].concat(),
+        content,
+    );
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "first  \nsecond  \n"
+    );
+    assert!(!temp_dir.path().join("README.md").exists());
+}
+
+#[test]
+fn stats_only_prints_the_totals_without_cleaning() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    create_tree(root_path);
+
+    let output = run_lineman(
+        root_path,
+        &[
+            "-p",
+            ".",
+            "-e",
+            "rs",
+            "py",
+            "--stats-only",
+            "--format",
+            "json",
+        ],
+    );
+    assert!(output.status.success());
+
+    let stats: Value = serde_json::from_slice(&output.stdout).unwrap();
+
+    assert_eq!(stats["files"], 4);
+    assert_eq!(stats["changed_files"], 2);
+    assert_eq!(stats["already_clean"], 1);
+    assert_eq!(stats["skipped"], 1);
+    assert!(stats.get("would_be_cleaned").is_none());
+    assert_eq!(
+        read(root_path, "src/main.rs"),
+        "fn main() {   \n    println!(\"hi\");\t\n}\n\n\n"
+    );
+}
+
+#[test]
+fn summary_json_is_printed_on_its_own_line_even_when_quiet() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    create_tree(root_path);
+
+    let output = run_lineman(
+        root_path,
+        &["-p", ".", "-e", "rs", "py", "--quiet", "--summary-json"],
+    );
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(stdout.lines().count(), 1);
+    assert!(stdout.starts_with(r#"{"cleaned":2,"skipped":1,"errors":0,"bytes_removed":"#));
+}
+
+#[test]
+fn the_cache_only_skips_files_clean_under_their_own_options() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    fs::write(root_path.join("a.md"), "hard  \n").unwrap();
+    let args = ["-p", ".", "-e", "md", "json", "--cache", "cache.json"];
+
+    assert!(
+        run_lineman(root_path, &[&args[..], &["--markdown"]].concat())
+            .status
+            .success()
+    );
+    assert_eq!(read(root_path, "a.md"), "hard  \n");
+
+    let output = run_lineman(
+        root_path,
+        &[
+            &args[..],
+            &["--check", "--format", "json", "--relative-paths"],
+        ]
+        .concat(),
+    );
+    assert_eq!(output.status.code(), Some(1));
+
+    // The cache itself sits in the tree without a final newline, but is never selected
+    let report: Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        report["would_be_cleaned"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|cleaned_file| cleaned_file["path"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        vec!["a.md"]
+    );
+}
+
+#[test]
+fn usage_errors_exit_with_the_invalid_argument_status() {
+    let temp_dir = tempfile::tempdir().unwrap();
+
+    let output = run_lineman(temp_dir.path(), &["-p", ".", "--quiet", "--verbose"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("cannot be used with"));
+
+    assert_eq!(
+        run_lineman(temp_dir.path(), &["--help"]).status.code(),
+        Some(0)
+    );
+}
+
+#[test]
+fn a_negative_job_count_is_an_invalid_argument() {
+    let temp_dir = tempfile::tempdir().unwrap();
+
+    let output = run_lineman(temp_dir.path(), &["-p", ".", "--jobs", "-1"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8(output.stderr)
+        .unwrap()
+        .contains("can't be negative"));
+}
+
+#[test]
+fn github_annotations_leave_the_files_they_report_untouched() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let root_path = temp_dir.path();
+    create_tree(root_path);
+
+    let output = run_lineman(root_path, &["-p", ".", "-e", "rs", "--format", "github"]);
+
+    assert!(String::from_utf8(output.stdout)
+        .unwrap()
+        .contains("::warning file="));
+    assert_eq!(
+        read(root_path, "src/main.rs"),
+        "fn main() {   \n    println!(\"hi\");\t\n}\n\n\n"
+    );
+}
+
+#[test]
+fn missing_stdin_paths_are_counted_as_the_errors_that_fail_the_run() {
+    let temp_dir = tempfile::tempdir().unwrap();
+    fs::write(temp_dir.path().join("main.rs"), "fn main() {}\n").unwrap();
+
+    let output =
+        run_lineman_with_stdin(temp_dir.path(), &["--stdin-paths"], "main.rs\nmissing.rs\n");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+
+    assert_eq!(output.status.code(), Some(5));
+    assert!(stdout.contains("Skipped Missing Files (Not Found):"));
+    assert!(stdout.contains("skipped 0, errors 1"));
+}